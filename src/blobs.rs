@@ -0,0 +1,204 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+use log::info;
+use log::warn;
+use reth_primitives::constants::eip4844::MAINNET_KZG_TRUSTED_SETUP;
+use reth_primitives::BlobTransactionSidecar;
+use reth_primitives::SealedBlock;
+use reth_primitives::TransactionSigned;
+use reth_primitives::TxHash;
+use reth_rpc_types::engine::BlobsBundleV1;
+
+use crate::error::PbbError;
+use crate::rpc::eth_get_blob_sidecars;
+
+/// Verified sidecars of the blob transactions seen during a slot, by transaction hash.
+///
+/// A blob transaction can only go into a payload once its sidecar is known, the
+/// proposer has to publish the blobs along with the block.
+#[derive(Debug, Default)]
+pub struct BlobStore {
+    sidecars: HashMap<TxHash, BlobTransactionSidecar>,
+    /// Transactions whose sidecar doesn't match their versioned hashes
+    invalid: HashSet<TxHash>,
+}
+
+impl BlobStore {
+    /// Fetches and verifies the sidecars of the blob transactions of `txs` that aren't
+    /// known yet. Sidecars the node doesn't return are requested again on the next call.
+    pub async fn fetch_missing<'a>(
+        &mut self,
+        rpc_url: &str,
+        txs: impl IntoIterator<Item = &'a TransactionSigned>,
+    ) -> Result<(), PbbError> {
+        let missing: Vec<&TransactionSigned> = txs
+            .into_iter()
+            .filter(|tx| tx.blob_versioned_hashes().is_some())
+            .filter(|tx| {
+                !self.sidecars.contains_key(&tx.hash()) && !self.invalid.contains(&tx.hash())
+            })
+            .collect();
+        if missing.is_empty() {
+            return Ok(());
+        }
+
+        let hashes: Vec<TxHash> = missing.iter().map(|tx| tx.hash()).collect();
+        let sidecars = eth_get_blob_sidecars(rpc_url, &hashes).await?.result;
+        if sidecars.len() != missing.len() {
+            return Err(PbbError::BlobSidecarCount {
+                requested: missing.len(),
+                received: sidecars.len(),
+            });
+        }
+        for (tx, sidecar) in missing.into_iter().zip(sidecars) {
+            let Some(sidecar) = sidecar else {
+                continue;
+            };
+            match verify_sidecar(tx, &sidecar) {
+                Ok(()) => {
+                    self.sidecars.insert(tx.hash(), sidecar);
+                }
+                Err(e) => {
+                    warn!("Invalid blob sidecar for tx {}: {}", tx.hash(), e);
+                    self.invalid.insert(tx.hash());
+                }
+            }
+        }
+        info!("{} blob sidecars known", self.sidecars.len());
+        Ok(())
+    }
+
+    /// Returns true if the transaction carries no blobs or its sidecar is known
+    pub fn has_sidecar(&self, tx: &TransactionSigned) -> bool {
+        tx.blob_versioned_hashes().is_none() || self.sidecars.contains_key(&tx.hash())
+    }
+
+    /// Assembles the blobs, commitments and proofs of the blob transactions of `block`,
+    /// in block order
+    pub fn blobs_bundle(&self, block: &SealedBlock) -> Result<BlobsBundleV1, PbbError> {
+        let mut bundle = BlobsBundleV1 {
+            commitments: Vec::new(),
+            proofs: Vec::new(),
+            blobs: Vec::new(),
+        };
+        for tx in block
+            .body
+            .iter()
+            .filter(|tx| tx.blob_versioned_hashes().is_some())
+        {
+            let sidecar = self
+                .sidecars
+                .get(&tx.hash())
+                .ok_or(PbbError::MissingBlobSidecar { tx_hash: tx.hash() })?;
+            bundle
+                .commitments
+                .extend(sidecar.commitments.iter().cloned());
+            bundle.proofs.extend(sidecar.proofs.iter().cloned());
+            bundle.blobs.extend(sidecar.blobs.iter().cloned());
+        }
+        Ok(bundle)
+    }
+}
+
+/// Checks that the KZG commitments of `sidecar` match the versioned hashes of the blob
+/// transaction `tx` and that its proofs hold
+pub fn verify_sidecar(
+    tx: &TransactionSigned,
+    sidecar: &BlobTransactionSidecar,
+) -> Result<(), PbbError> {
+    let blob_tx = tx
+        .as_eip4844()
+        .ok_or(PbbError::NotBlobTransaction { tx_hash: tx.hash() })?;
+    blob_tx
+        .validate_blob(sidecar, &MAINNET_KZG_TRUSTED_SETUP)
+        .map_err(PbbError::InvalidBlobSidecar)
+}
+
+#[cfg(test)]
+mod tests {
+    use reth_primitives::kzg::Blob;
+    use reth_primitives::kzg::KzgCommitment;
+    use reth_primitives::kzg::KzgProof;
+    use reth_primitives::kzg::BYTES_PER_BLOB;
+    use reth_primitives::Address;
+    use reth_primitives::FixedBytes;
+    use reth_primitives::Signature;
+    use reth_primitives::Transaction;
+    use reth_primitives::TxEip4844;
+    use reth_primitives::B256;
+    use sha2::Digest;
+    use sha2::Sha256;
+
+    use super::*;
+
+    /// Version byte of the versioned hash of a KZG commitment
+    const VERSIONED_HASH_VERSION_KZG: u8 = 0x01;
+
+    /// Sidecar of a single blob whose first field element is `value`
+    fn sidecar(value: u8) -> BlobTransactionSidecar {
+        let mut bytes = [0u8; BYTES_PER_BLOB];
+        bytes[31] = value;
+        let blob = Blob::new(bytes);
+        let commitment = KzgCommitment::blob_to_kzg_commitment(&blob, &MAINNET_KZG_TRUSTED_SETUP)
+            .unwrap()
+            .to_bytes();
+        let proof =
+            KzgProof::compute_blob_kzg_proof(&blob, &commitment, &MAINNET_KZG_TRUSTED_SETUP)
+                .unwrap()
+                .to_bytes();
+        BlobTransactionSidecar {
+            blobs: vec![FixedBytes::from(bytes)],
+            commitments: vec![FixedBytes::from(commitment.into_inner())],
+            proofs: vec![FixedBytes::from(proof.into_inner())],
+        }
+    }
+
+    fn versioned_hash(commitment: &[u8]) -> B256 {
+        let mut hash: [u8; 32] = Sha256::digest(commitment).into();
+        hash[0] = VERSIONED_HASH_VERSION_KZG;
+        B256::from(hash)
+    }
+
+    /// Blob transaction carrying the blobs of `versioned_hashes`
+    fn blob_tx(versioned_hashes: Vec<B256>) -> TransactionSigned {
+        TransactionSigned::from_transaction_and_signature(
+            Transaction::Eip4844(TxEip4844 {
+                chain_id: 1,
+                to: Address::repeat_byte(0xee),
+                blob_versioned_hashes: versioned_hashes,
+                ..Default::default()
+            }),
+            Signature::default(),
+        )
+    }
+
+    #[test]
+    fn accepts_the_sidecar_of_the_transaction() {
+        let valid = sidecar(1);
+        let tx = blob_tx(vec![versioned_hash(valid.commitments[0].as_slice())]);
+        assert!(verify_sidecar(&tx, &valid).is_ok());
+    }
+
+    #[test]
+    fn rejects_a_commitment_that_does_not_match_the_blob() {
+        // the commitment of another blob, which the transaction does commit to
+        let mut mismatched = sidecar(1);
+        mismatched.commitments = sidecar(2).commitments;
+        let tx = blob_tx(vec![versioned_hash(mismatched.commitments[0].as_slice())]);
+        assert!(matches!(
+            verify_sidecar(&tx, &mismatched),
+            Err(PbbError::InvalidBlobSidecar(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_a_versioned_hash_that_does_not_match_the_commitment() {
+        let other = sidecar(2);
+        let tx = blob_tx(vec![versioned_hash(other.commitments[0].as_slice())]);
+        assert!(matches!(
+            verify_sidecar(&tx, &sidecar(1)),
+            Err(PbbError::InvalidBlobSidecar(_))
+        ));
+    }
+}
@@ -0,0 +1,137 @@
+use reth_primitives::constants::BEACON_NONCE;
+use reth_primitives::constants::EMPTY_OMMER_ROOT_HASH;
+use reth_primitives::proofs;
+use reth_primitives::Address;
+use reth_primitives::Block;
+use reth_primitives::Bytes;
+use reth_primitives::Header;
+use reth_primitives::ReceiptWithBloom;
+use reth_primitives::SealedBlock;
+use reth_primitives::SealedHeader;
+use reth_primitives::TransactionSigned;
+use reth_primitives::TxType;
+use reth_primitives::Withdrawals;
+use reth_primitives::B256;
+use reth_primitives::U256;
+use reth_rpc_types::engine::PayloadAttributes;
+
+use crate::bundle::Bundle;
+use crate::bundle::ExcludedBundle;
+use crate::error::PbbError;
+use crate::receipts::block_logs_bloom;
+use crate::report::BuildReport;
+use crate::reth::RejectedTx;
+use crate::selection::ExcludedTx;
+
+/// Block built by the builder together with the receipts of its transactions
+#[derive(Debug, Clone)]
+pub struct BuiltBlock {
+    pub block: SealedBlock,
+    /// Signer of each transaction of the block, in order
+    pub senders: Vec<Address>,
+    pub receipts: Vec<ReceiptWithBloom>,
+    /// Value of the block and of each of its transactions and bundles
+    pub report: BuildReport,
+    /// Bundles included at the top of the block, in order
+    pub bundles: Vec<Bundle>,
+    /// Transactions left out of the block and why
+    pub excluded: Vec<ExcludedTx>,
+    /// Transactions the EVM refused to execute, pruned from the block
+    pub rejected: Vec<RejectedTx>,
+    /// Bundles left out of the block and why
+    pub excluded_bundles: Vec<ExcludedBundle>,
+}
+
+impl BuiltBlock {
+    /// Returns what the block is worth to the proposer: the builder payment in
+    /// builder-key mode, the coinbase balance delta otherwise
+    pub fn value(&self) -> U256 {
+        self.report.proposer_value()
+    }
+
+    /// Returns the transactions left out that could fit a later block as is
+    pub fn deferred(&self) -> impl Iterator<Item = &ExcludedTx> {
+        self.excluded.iter().filter(|tx| tx.reason.is_deferred())
+    }
+
+    /// Returns the transactions left out that can't go into any block on top of the
+    /// same parent
+    pub fn dropped(&self) -> impl Iterator<Item = &ExcludedTx> {
+        self.excluded.iter().filter(|tx| !tx.reason.is_deferred())
+    }
+}
+
+/// Assembles a sealed block on top of `parent` from the transactions executed by pevm.
+///
+/// `receipts` must hold one receipt per transaction, in the same order.
+pub fn assemble_block(
+    parent: &SealedHeader,
+    attributes: &PayloadAttributes,
+    block_env: &pevm::BlockEnv,
+    transactions: Vec<TransactionSigned>,
+    receipts: &[ReceiptWithBloom],
+    state_root: B256,
+    extra_data: Bytes,
+) -> Result<SealedBlock, PbbError> {
+    let header_field = |field, value: U256| {
+        u64::try_from(value).map_err(|_| PbbError::HeaderOverflow { field, value })
+    };
+    let gas_limit = header_field("gas limit", block_env.gas_limit)?;
+    let base_fee = header_field("base fee", block_env.basefee)?;
+    let gas_used = receipts
+        .last()
+        .map(|r| r.receipt.cumulative_gas_used)
+        .unwrap_or_default();
+    let logs_bloom = block_logs_bloom(receipts);
+
+    // blob fields are only present once the parent block is past Cancun
+    let (blob_gas_used, excess_blob_gas) = match block_env.blob_excess_gas_and_price {
+        Some(ref blob) => {
+            let blob_gas_used = transactions
+                .iter()
+                .filter(|tx| tx.tx_type() == TxType::Eip4844)
+                .filter_map(|tx| tx.blob_gas_used())
+                .sum::<u64>();
+            (Some(blob_gas_used), Some(blob.excess_blob_gas))
+        }
+        None => (None, None),
+    };
+
+    let withdrawals = attributes.withdrawals.clone().map(Withdrawals::new);
+    let withdrawals_root = withdrawals
+        .as_ref()
+        .map(|withdrawals| proofs::calculate_withdrawals_root(withdrawals));
+
+    let header = Header {
+        parent_hash: parent.hash(),
+        ommers_hash: EMPTY_OMMER_ROOT_HASH,
+        beneficiary: block_env.coinbase,
+        state_root,
+        transactions_root: proofs::calculate_transaction_root(&transactions),
+        receipts_root: proofs::calculate_receipt_root(receipts),
+        withdrawals_root,
+        logs_bloom,
+        difficulty: U256::ZERO,
+        number: parent.number + 1,
+        gas_limit,
+        gas_used,
+        timestamp: attributes.timestamp,
+        mix_hash: attributes.prev_randao,
+        nonce: BEACON_NONCE,
+        base_fee_per_gas: Some(base_fee),
+        blob_gas_used,
+        excess_blob_gas,
+        parent_beacon_block_root: attributes.parent_beacon_block_root,
+        extra_data,
+        ..Default::default()
+    };
+
+    Ok(Block {
+        header,
+        body: transactions,
+        ommers: Vec::new(),
+        withdrawals,
+        requests: None,
+    }
+    .seal_slow())
+}
@@ -0,0 +1,375 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::num::NonZeroUsize;
+use std::ops::Deref;
+use std::sync::Arc;
+use std::sync::RwLock;
+use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use futures_util::stream::StreamExt;
+use log::info;
+use log::warn;
+use reth_primitives::TransactionSignedEcRecovered;
+use reth_primitives::TxHash;
+use reth_primitives::U256;
+use reth_provider::ChainSpecProvider;
+use reth_provider::HeaderProvider;
+use reth_rpc_types::beacon::events::PayloadAttributesEvent;
+use reth_rpc_types::engine::BlobsBundleV1;
+use reth_rpc_types::engine::PayloadId;
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+
+use crate::blobs::BlobStore;
+use crate::block::BuiltBlock;
+use crate::bundle::Bundle;
+use crate::engine::payload_id;
+use crate::error::PbbError;
+use crate::lighthouse::BeaconEventsConfig;
+use crate::order_pool::SharedOrderPool;
+use crate::pbb::build_block;
+use crate::pbb::BuildOptions;
+use crate::recovery::SenderCache;
+use crate::relay::Proposer;
+use crate::relay::RelayClient;
+use crate::reth_db::RethProvider;
+use crate::rpc::eth_get_best_transactions;
+use crate::utils::retain_chain_transactions;
+
+/// Settings of the long running block builder
+#[derive(Debug, Clone)]
+pub struct BuilderConfig {
+    /// Execution layer http RPC url used to fetch mempool transactions
+    pub rpc_url: String,
+    /// How often the block of the open slot is rebuilt
+    pub rebuild_interval: Duration,
+    /// Number of threads used by pevm
+    pub concurrency_level: NonZeroUsize,
+    /// Relays every improved block is submitted to
+    pub relay: Option<RelayClient>,
+    /// How the block of every slot is built
+    pub build_options: BuildOptions,
+}
+
+/// Number of slots for which built payloads are kept around
+const RETAINED_SLOTS: u64 = 2;
+
+/// Most valuable block built for a slot
+#[derive(Debug, Clone)]
+pub struct SlotPayload {
+    pub slot: u64,
+    pub built: BuiltBlock,
+    pub value: U256,
+    /// Blobs of the blob transactions of the block, published along with it
+    pub blobs_bundle: BlobsBundleV1,
+}
+
+/// Best payload of every recent build job, keyed by payload id
+pub type PayloadStore = Arc<RwLock<HashMap<PayloadId, SlotPayload>>>;
+
+/// Service that builds a block for every slot announced by the beacon node
+pub struct BlockBuilder {
+    provider: Arc<RethProvider>,
+    beacon: BeaconEventsConfig,
+    config: BuilderConfig,
+    payloads: PayloadStore,
+    orders: SharedOrderPool,
+}
+
+impl BlockBuilder {
+    /// Creates a new builder service
+    pub fn new(
+        provider: Arc<RethProvider>,
+        beacon: BeaconEventsConfig,
+        config: BuilderConfig,
+    ) -> Self {
+        Self {
+            provider,
+            beacon,
+            config,
+            payloads: PayloadStore::default(),
+            orders: SharedOrderPool::default(),
+        }
+    }
+
+    /// Returns a handle to the best payloads built so far
+    pub fn payloads(&self) -> PayloadStore {
+        self.payloads.clone()
+    }
+
+    /// Returns a handle to the pool of bundles and MEV-Share hints considered for the next blocks
+    pub fn orders(&self) -> SharedOrderPool {
+        self.orders.clone()
+    }
+
+    /// Starts a build job on every payload attributes event, cancelling the previous one
+    pub async fn run(self) -> Result<(), PbbError> {
+        let mut subscription = self.beacon.subscribe().await;
+        let mut current_job: Option<CancellationToken> = None;
+
+        loop {
+            let event = match subscription.next().await {
+                Some(Ok(event)) => event,
+                Some(Err(e)) => {
+                    warn!("Payload attributes stream failed: {:?}, resubscribing", e);
+                    subscription = self.beacon.subscribe().await;
+                    continue;
+                }
+                None => {
+                    warn!("Payload attributes stream ended, resubscribing");
+                    subscription = self.beacon.subscribe().await;
+                    continue;
+                }
+            };
+
+            if let Some(job) = current_job.take() {
+                job.cancel();
+            }
+            let slot = event.data.proposal_slot;
+            self.payloads
+                .write()
+                .unwrap()
+                .retain(|_, payload| payload.slot + RETAINED_SLOTS > slot);
+            if let Ok(Some(parent)) = self.provider.header(&event.data.parent_block_hash) {
+                self.orders
+                    .write()
+                    .unwrap()
+                    .set_block_number(parent.number + 1);
+            }
+
+            let cancel = CancellationToken::new();
+            tokio::spawn(build_job(
+                self.provider.clone(),
+                self.beacon.clone(),
+                self.config.clone(),
+                event,
+                self.payloads.clone(),
+                self.orders.clone(),
+                cancel.clone(),
+            ));
+            current_job = Some(cancel);
+        }
+    }
+}
+
+/// Rebuilds the block of a slot on every tick until the slot starts or the job is cancelled
+async fn build_job(
+    provider: Arc<RethProvider>,
+    beacon: BeaconEventsConfig,
+    config: BuilderConfig,
+    event: PayloadAttributesEvent,
+    payloads: PayloadStore,
+    orders: SharedOrderPool,
+    cancel: CancellationToken,
+) {
+    let slot = event.data.proposal_slot;
+    let proposer_index = event.data.proposer_index;
+    let attributes = event.data.payload_attributes;
+    let parent_hash = event.data.parent_block_hash;
+    let deadline = slot_deadline(attributes.timestamp);
+    let payload_id = payload_id(&parent_hash, &attributes);
+
+    let parent = match provider.header(&parent_hash) {
+        Ok(Some(header)) => header.seal(parent_hash),
+        Ok(None) => {
+            warn!(
+                "Parent {} of slot {} is not in the database",
+                parent_hash, slot
+            );
+            return;
+        }
+        Err(e) => {
+            warn!(
+                "Error fetching parent {} of slot {}: {:?}",
+                parent_hash, slot, e
+            );
+            return;
+        }
+    };
+    info!(
+        "Starting build job {} for slot {} on top of {}",
+        payload_id, slot, parent_hash
+    );
+
+    let proposer = match &config.relay {
+        Some(_) => match beacon.validator_pubkey(proposer_index).await {
+            Ok(pubkey) => Some(Proposer {
+                pubkey,
+                fee_recipient: attributes.suggested_fee_recipient,
+            }),
+            Err(e) => {
+                warn!(
+                    "Error fetching the key of proposer {} of slot {}, not submitting to relays: {:?}",
+                    proposer_index, slot, e
+                );
+                None
+            }
+        },
+        None => None,
+    };
+
+    let mut interval = tokio::time::interval(config.rebuild_interval);
+    let mut last_hashes: Option<Vec<TxHash>> = None;
+    let mut blobs = BlobStore::default();
+    let mut senders = SenderCache::default();
+    // transactions that can't go into any block on top of `parent`, deferred ones are
+    // considered again on every rebuild
+    let mut dropped: HashSet<TxHash> = HashSet::new();
+    loop {
+        tokio::select! {
+            _ = cancel.cancelled() => {
+                info!("Build job for slot {} cancelled", slot);
+                return;
+            }
+            _ = tokio::time::sleep_until(deadline) => {
+                info!("Slot {} deadline reached, stopping its build job", slot);
+                return;
+            }
+            _ = interval.tick() => {}
+        }
+
+        let txs = match eth_get_best_transactions(&config.rpc_url).await {
+            Ok(response) => {
+                let fetched: Vec<_> = response
+                    .result
+                    .into_iter()
+                    .filter(|tx| !dropped.contains(&tx.hash()))
+                    .collect();
+                let fetched_hashes: Vec<TxHash> = fetched.iter().map(|tx| tx.hash()).collect();
+                let txs = senders.recover(fetched);
+                // a transaction with an invalid signature never becomes valid
+                let recovered: HashSet<TxHash> = txs.iter().map(|tx| tx.hash()).collect();
+                dropped.extend(
+                    fetched_hashes
+                        .into_iter()
+                        .filter(|hash| !recovered.contains(hash)),
+                );
+                txs
+            }
+            Err(e) => {
+                warn!("Error fetching transactions: {:?}", e);
+                continue;
+            }
+        };
+        let mut slot_bundles = {
+            let mut orders = orders.write().unwrap();
+            orders.observe_mempool(&txs);
+            orders.bundles_for(parent.number + 1)
+        };
+        let bundle_txs = slot_bundles.iter().flat_map(|bundle| &bundle.txs);
+        if let Err(e) = blobs
+            .fetch_missing(
+                &config.rpc_url,
+                txs.iter().chain(bundle_txs).map(Deref::deref),
+            )
+            .await
+        {
+            warn!("Error fetching blob sidecars: {:?}", e);
+        }
+        // blob transactions can't be proposed without their blobs
+        let txs: Vec<TransactionSignedEcRecovered> =
+            txs.into_iter().filter(|tx| blobs.has_sidecar(tx)).collect();
+        slot_bundles.retain(|bundle| bundle.txs.iter().all(|tx| blobs.has_sidecar(tx)));
+        // only rebuild when the mempool or the bundles changed since the last build
+        let hashes: Vec<TxHash> = txs
+            .iter()
+            .map(|tx| tx.hash())
+            .chain(slot_bundles.iter().map(Bundle::hash))
+            .collect();
+        if last_hashes.as_ref() == Some(&hashes) {
+            continue;
+        }
+        last_hashes = Some(hashes);
+
+        let txs = retain_chain_transactions(txs, &provider.chain_spec());
+        let built = tokio::task::spawn_blocking({
+            let provider = provider.clone();
+            let parent = parent.clone();
+            let attributes = attributes.clone();
+            let concurrency_level = config.concurrency_level;
+            let build_options = config.build_options.clone();
+            move || {
+                build_block(
+                    &provider,
+                    &parent,
+                    &attributes,
+                    txs,
+                    slot_bundles,
+                    concurrency_level,
+                    &build_options,
+                )
+            }
+        })
+        .await;
+
+        let built = match built {
+            Ok(Ok(built)) => built,
+            Ok(Err(e)) => {
+                warn!("Error building block for slot {}: {:?}", slot, e);
+                continue;
+            }
+            Err(e) => {
+                warn!("Build task for slot {} panicked: {:?}", slot, e);
+                continue;
+            }
+        };
+        if cancel.is_cancelled() || Instant::now() >= deadline {
+            return;
+        }
+        dropped.extend(built.dropped().map(|tx| tx.hash));
+        dropped.extend(
+            built
+                .rejected
+                .iter()
+                .filter(|tx| tx.is_permanent())
+                .map(|tx| tx.hash),
+        );
+
+        let blobs_bundle = match blobs.blobs_bundle(&built.block) {
+            Ok(blobs_bundle) => blobs_bundle,
+            Err(e) => {
+                warn!("Error assembling the blobs of slot {}: {:?}", slot, e);
+                continue;
+            }
+        };
+        let value = built.value();
+        let mut store = payloads.write().unwrap();
+        let improves = store
+            .get(&payload_id)
+            .map_or(true, |current| value > current.value);
+        if improves {
+            info!(
+                "New best block {} for slot {} with value {}",
+                built.block.hash(),
+                slot,
+                value
+            );
+            let payload = SlotPayload {
+                slot,
+                built,
+                value,
+                blobs_bundle,
+            };
+            if let (Some(relay), Some(proposer)) = (config.relay.clone(), proposer) {
+                let payload = payload.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = relay.submit(&payload, &proposer).await {
+                        warn!("Error submitting block for slot {}: {:?}", slot, e);
+                    }
+                });
+            }
+            store.insert(payload_id, payload);
+        }
+    }
+}
+
+/// Returns the instant at which the slot starting at `timestamp` opens
+fn slot_deadline(timestamp: u64) -> Instant {
+    let slot_start = UNIX_EPOCH + Duration::from_secs(timestamp);
+    let remaining = slot_start
+        .duration_since(SystemTime::now())
+        .unwrap_or_default();
+    Instant::now() + remaining
+}
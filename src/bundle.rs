@@ -0,0 +1,214 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::path::Path;
+
+use reth_primitives::keccak256;
+use reth_primitives::Bytes;
+use reth_primitives::TransactionSigned;
+use reth_primitives::TransactionSignedEcRecovered;
+use reth_primitives::TxHash;
+use reth_primitives::B256;
+use reth_primitives::U64;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::error::PbbError;
+
+/// Bundle as sent by searchers to `eth_sendBundle`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RawBundle {
+    /// EIP-2718 encoded signed transactions, executed in this order
+    pub txs: Vec<Bytes>,
+    pub block_number: U64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_timestamp: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_timestamp: Option<u64>,
+    /// Transactions of the bundle allowed to revert without invalidating it
+    #[serde(default)]
+    pub reverting_tx_hashes: Vec<TxHash>,
+}
+
+/// Ordered transactions included all together at their position or not at all
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bundle {
+    hash: B256,
+    pub txs: Vec<TransactionSignedEcRecovered>,
+    /// Block the bundle targets
+    pub block_number: u64,
+    pub min_timestamp: Option<u64>,
+    pub max_timestamp: Option<u64>,
+    pub reverting_tx_hashes: HashSet<TxHash>,
+}
+
+impl Bundle {
+    /// Creates a bundle targeting `block_number`
+    pub fn new(
+        txs: Vec<TransactionSignedEcRecovered>,
+        block_number: u64,
+        reverting_tx_hashes: HashSet<TxHash>,
+    ) -> Self {
+        let hashes: Vec<u8> = txs.iter().flat_map(|tx| tx.hash().0).collect();
+        Self {
+            hash: keccak256(hashes),
+            txs,
+            block_number,
+            min_timestamp: None,
+            max_timestamp: None,
+            reverting_tx_hashes,
+        }
+    }
+
+    /// Sets the hash the bundle is known by, like the hash `mev_sendBundle` returned
+    /// for a backrun, which also covers the hint it backruns
+    pub fn with_hash(mut self, hash: B256) -> Self {
+        self.hash = hash;
+        self
+    }
+
+    /// Decodes the transactions of a raw bundle, checking their signatures
+    pub fn decode(raw: RawBundle) -> Result<Self, PbbError> {
+        let txs = raw
+            .txs
+            .iter()
+            .map(decode_signed)
+            .collect::<Result<Vec<_>, _>>()?;
+        if txs.is_empty() {
+            return Err(PbbError::EmptyBundle);
+        }
+
+        let mut bundle = Self::new(
+            txs,
+            raw.block_number.to(),
+            raw.reverting_tx_hashes.into_iter().collect(),
+        );
+        bundle.min_timestamp = raw.min_timestamp;
+        bundle.max_timestamp = raw.max_timestamp;
+        Ok(bundle)
+    }
+
+    /// Returns the bundle hash, the keccak of the concatenated hashes of its transactions
+    /// unless set with [`Bundle::with_hash`]
+    pub fn hash(&self) -> B256 {
+        self.hash
+    }
+
+    /// Returns true if the transaction may revert without invalidating the bundle
+    pub fn can_revert(&self, tx_hash: &TxHash) -> bool {
+        self.reverting_tx_hashes.contains(tx_hash)
+    }
+
+    /// Returns true if one of the transactions of the bundle is `tx_hash`
+    pub fn contains(&self, tx_hash: &TxHash) -> bool {
+        self.txs.iter().any(|tx| tx.hash() == *tx_hash)
+    }
+
+    /// Sum of the gas limits of the transactions of the bundle
+    pub fn gas_limit(&self) -> u64 {
+        self.txs.iter().map(|tx| tx.gas_limit()).sum()
+    }
+
+    /// Number of blobs carried by the transactions of the bundle
+    pub fn blob_count(&self) -> u64 {
+        self.txs
+            .iter()
+            .filter_map(|tx| tx.blob_versioned_hashes())
+            .map(|hashes| hashes.len() as u64)
+            .sum()
+    }
+
+    /// Checks that the bundle can go into the block `block_number` at `timestamp`
+    pub fn check_target(
+        &self,
+        block_number: u64,
+        timestamp: u64,
+    ) -> Result<(), BundleExclusionReason> {
+        if self.block_number != block_number {
+            return Err(BundleExclusionReason::WrongBlock {
+                target: self.block_number,
+                block_number,
+            });
+        }
+        let too_early = self.min_timestamp.is_some_and(|min| timestamp < min);
+        let too_late = self
+            .max_timestamp
+            .is_some_and(|max| max != 0 && timestamp > max);
+        if too_early || too_late {
+            return Err(BundleExclusionReason::Timestamp { timestamp });
+        }
+        Ok(())
+    }
+}
+
+/// Why a bundle was left out of the block
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BundleExclusionReason {
+    /// The bundle targets another block
+    WrongBlock { target: u64, block_number: u64 },
+    /// The block timestamp is outside of the bundle timestamp range
+    Timestamp { timestamp: u64 },
+    /// The bundle does not fit in the gas left in the block
+    GasLimit { gas_limit: u64, remaining: u64 },
+    /// The blobs of the bundle exceed the blobs left in the block
+    BlobLimit { blobs: u64, remaining: u64 },
+    /// A transaction of the bundle is already in an earlier bundle
+    DuplicateTransaction { tx_hash: TxHash },
+    /// The EVM refused to execute a transaction of the bundle
+    InvalidTransaction { tx_hash: TxHash, error: String },
+    /// A transaction of the bundle that isn't allowed to revert reverted
+    Reverted { tx_hash: TxHash },
+}
+
+impl fmt::Display for BundleExclusionReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::WrongBlock {
+                target,
+                block_number,
+            } => write!(f, "targets block {target} instead of {block_number}"),
+            Self::Timestamp { timestamp } => {
+                write!(f, "block timestamp {timestamp} out of the bundle range")
+            }
+            Self::GasLimit {
+                gas_limit,
+                remaining,
+            } => write!(f, "gas limit {gas_limit} exceeds the {remaining} gas left"),
+            Self::BlobLimit { blobs, remaining } => {
+                write!(f, "{blobs} blobs exceed the {remaining} blobs left")
+            }
+            Self::DuplicateTransaction { tx_hash } => {
+                write!(f, "tx {tx_hash} is already in another bundle")
+            }
+            Self::InvalidTransaction { tx_hash, error } => {
+                write!(f, "tx {tx_hash} is invalid: {error}")
+            }
+            Self::Reverted { tx_hash } => write!(f, "tx {tx_hash} reverted"),
+        }
+    }
+}
+
+/// Bundle left out of a block
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExcludedBundle {
+    pub hash: B256,
+    pub reason: BundleExclusionReason,
+}
+
+/// Decodes an EIP-2718 encoded signed transaction and recovers its signer
+pub fn decode_signed(encoded: &Bytes) -> Result<TransactionSignedEcRecovered, PbbError> {
+    let tx = TransactionSigned::decode_enveloped(&mut encoded.as_ref())?;
+    let tx_hash = tx.hash();
+    tx.into_ecrecovered()
+        .ok_or(PbbError::SignatureRecovery { tx_hash })
+}
+
+/// Reads a JSON array of `eth_sendBundle` bundles from `path`
+pub fn load_bundles(path: &Path) -> Result<Vec<Bundle>, PbbError> {
+    let raw = std::fs::read_to_string(path).map_err(|source| PbbError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let bundles: Vec<RawBundle> = serde_json::from_str(&raw)?;
+    bundles.into_iter().map(Bundle::decode).collect()
+}
@@ -0,0 +1,82 @@
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use reth_chainspec::ChainSpec;
+use reth_chainspec::HOLESKY;
+use reth_chainspec::MAINNET;
+use reth_chainspec::SEPOLIA;
+use reth_primitives::Genesis;
+
+use crate::error::PbbError;
+
+/// Network the builder runs on, either a named network or a custom genesis file
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum ChainSelection {
+    Mainnet,
+    Sepolia,
+    #[default]
+    Holesky,
+    /// Path to a genesis JSON file of a private network
+    Genesis(PathBuf),
+}
+
+impl ChainSelection {
+    /// Returns the chain spec of the selected network
+    pub fn chain_spec(&self) -> Result<Arc<ChainSpec>, PbbError> {
+        Ok(match self {
+            Self::Mainnet => MAINNET.clone(),
+            Self::Sepolia => SEPOLIA.clone(),
+            Self::Holesky => HOLESKY.clone(),
+            Self::Genesis(path) => {
+                let chain_spec_error = |reason: String| PbbError::ChainSpec {
+                    path: path.clone(),
+                    reason,
+                };
+                let raw =
+                    std::fs::read_to_string(path).map_err(|e| chain_spec_error(e.to_string()))?;
+                let genesis: Genesis =
+                    serde_json::from_str(&raw).map_err(|e| chain_spec_error(e.to_string()))?;
+                Arc::new(genesis.into())
+            }
+        })
+    }
+
+    /// Returns the genesis fork version of the beacon chain of the network, which
+    /// builder signatures commit to. It is unknown for custom networks, whose beacon
+    /// chain config isn't part of the genesis file.
+    pub fn genesis_fork_version(&self) -> Option<[u8; 4]> {
+        match self {
+            Self::Mainnet => Some([0x00, 0x00, 0x00, 0x00]),
+            Self::Sepolia => Some([0x90, 0x00, 0x00, 0x69]),
+            Self::Holesky => Some([0x01, 0x01, 0x70, 0x00]),
+            Self::Genesis(_) => None,
+        }
+    }
+}
+
+impl FromStr for ChainSelection {
+    type Err = std::convert::Infallible;
+
+    /// Parses a network name, anything else is taken as the path to a genesis file
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "mainnet" => Self::Mainnet,
+            "sepolia" => Self::Sepolia,
+            "holesky" => Self::Holesky,
+            path => Self::Genesis(PathBuf::from(path)),
+        })
+    }
+}
+
+impl fmt::Display for ChainSelection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Mainnet => write!(f, "mainnet"),
+            Self::Sepolia => write!(f, "sepolia"),
+            Self::Holesky => write!(f, "holesky"),
+            Self::Genesis(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
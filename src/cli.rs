@@ -0,0 +1,418 @@
+use std::net::SocketAddr;
+use std::num::NonZeroUsize;
+use std::path::Path;
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+use clap::Parser;
+use clap::Subcommand;
+use log::info;
+use reth_primitives::FixedBytes;
+use reth_primitives::U256;
+use reth_provider::BlockReader;
+use reth_provider::BlockReaderIdExt;
+use reth_provider::ChainSpecProvider;
+use reth_provider::HeaderProvider;
+use reth_rpc_types::engine::PayloadAttributes;
+
+use crate::builder::BlockBuilder;
+use crate::builder::BuilderConfig;
+use crate::bundle::load_bundles;
+use crate::chain::ChainSelection;
+use crate::compare::compare_execution;
+use crate::engine::start_engine_server;
+use crate::error::PbbError;
+use crate::lighthouse::BeaconEventsConfig;
+use crate::mev_share::run_hint_stream;
+use crate::mock_relay::MockRelay;
+use crate::order_pool::start_order_server;
+use crate::payment::BuilderPayment;
+use crate::pbb::build_block;
+use crate::pbb::execute_pevm;
+use crate::pbb::run_pevm;
+use crate::pbb::BuildOptions;
+use crate::receipts::export_block_receipts;
+use crate::recovery::recover_all_signers;
+use crate::recovery::recover_signers;
+use crate::relay::BuilderSigner;
+use crate::relay::RelayClient;
+use crate::relay::SubmissionEncoding;
+use crate::report::export_build_report;
+use crate::reth::execute_reth_with;
+use crate::reth_db::default_datadir;
+use crate::reth_db::reth_db_provider;
+use crate::rpc::eth_get_best_transactions;
+use crate::utils::retain_chain_transactions;
+
+#[derive(Debug, Clone, Parser)]
+#[command(
+    name = "pbb-poc",
+    about = "Parallel block builder on top of pevm and reth"
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+    /// Reth data directory, defaults to the reth platform default of the selected chain
+    #[arg(long)]
+    pub datadir: Option<PathBuf>,
+    /// Reth static files directory, defaults to `<datadir>/static_files`
+    #[arg(long = "static-files")]
+    pub static_files: Option<PathBuf>,
+    /// Execution layer http RPC url used to fetch mempool transactions
+    #[arg(long = "rpc-url", default_value = "http://localhost:8545/")]
+    pub rpc_url: String,
+    /// Chain to build on: mainnet, sepolia, holesky or the path to a genesis file
+    #[arg(long, default_value = "holesky")]
+    pub chain: ChainSelection,
+    /// Hex encoded genesis fork version of the beacon chain bids are signed for, like
+    /// 0x10000038. Required with a custom genesis file, overrides the version of a
+    /// named chain
+    #[arg(long = "genesis-fork-version")]
+    pub genesis_fork_version: Option<FixedBytes<4>>,
+    /// Number of threads used by pevm, defaults to the available parallelism
+    #[arg(long)]
+    pub concurrency: Option<NonZeroUsize>,
+    /// File holding the hex encoded secp256k1 key of the builder. When set, the builder
+    /// is the block coinbase and pays the proposer with a transfer at the end of the block
+    #[arg(long = "builder-key-file")]
+    pub builder_key_file: Option<PathBuf>,
+    /// Part of the coinbase profit in wei the builder keeps in builder-key mode
+    #[arg(long = "payment-margin", default_value = "0")]
+    pub payment_margin: U256,
+    /// Leaves out the reverting transactions that pay the coinbase less than this many wei
+    #[arg(long = "min-reverted-tx-fee")]
+    pub min_reverted_tx_fee: Option<U256>,
+    #[command(flatten)]
+    pub beacon: BeaconEventsConfig,
+}
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum Command {
+    /// Build a block out of the mempool for the next payload attributes
+    Build {
+        /// Writes the receipts of the built block as JSON to this file
+        #[arg(long = "receipts-out")]
+        receipts_out: Option<PathBuf>,
+        /// Writes the value of the built block, per transaction and per bundle, as JSON to this file
+        #[arg(long = "report-out")]
+        report_out: Option<PathBuf>,
+        /// JSON file with an array of `eth_sendBundle` bundles to include
+        #[arg(long)]
+        bundles: Option<PathBuf>,
+    },
+    /// Execute the mempool with both pevm and reth and report the first difference
+    Compare,
+    /// Rebuild an existing block with pevm and check it against the canonical one
+    Replay {
+        /// Number of the block to replay
+        #[arg(long)]
+        block: u64,
+    },
+    /// Keep building a block for every slot announced by the beacon node
+    Run {
+        /// Interval in milliseconds at which the block of the open slot is rebuilt
+        #[arg(long = "rebuild-interval", default_value_t = 500)]
+        rebuild_interval_ms: u64,
+        /// Serves the built payloads over `engine_getPayload` on this address
+        #[arg(long = "engine-addr")]
+        engine_addr: Option<SocketAddr>,
+        /// Relay every improved block is submitted to, can be repeated
+        #[arg(long = "relay-url")]
+        relay_urls: Vec<String>,
+        /// File holding the hex encoded BLS secret key the bids are signed with, required
+        /// to submit to relays
+        #[arg(long = "builder-secret-key-file")]
+        builder_secret_key_file: Option<PathBuf>,
+        /// Encoding of the relay submissions: json or ssz
+        #[arg(long = "relay-encoding", default_value_t = SubmissionEncoding::Json)]
+        relay_encoding: SubmissionEncoding,
+        /// Starts an in-process mock relay on this address and submits to it
+        #[arg(long = "mock-relay-addr")]
+        mock_relay_addr: Option<SocketAddr>,
+        /// JSON file with an array of `eth_sendBundle` bundles to include in their target block
+        #[arg(long)]
+        bundles: Option<PathBuf>,
+        /// Accepts `eth_sendBundle` and `mev_sendBundle` bundles on this address
+        #[arg(long = "bundle-rpc-addr")]
+        bundle_rpc_addr: Option<SocketAddr>,
+        /// MEV-Share event stream to ingest hints from, like `https://mev-share.flashbots.net`
+        #[arg(long = "mev-share-url")]
+        mev_share_url: Option<String>,
+    },
+    /// Time pevm against the sequential reth executor on the mempool
+    Bench {
+        /// Number of times each executor runs the transactions
+        #[arg(long, default_value_t = 10)]
+        iterations: u32,
+    },
+}
+
+impl Cli {
+    /// Runs the selected command
+    pub async fn run(self) -> eyre::Result<()> {
+        let chain_spec = self.chain.chain_spec()?;
+        let datadir = match self.datadir {
+            Some(datadir) => datadir,
+            None => default_datadir(&chain_spec)?,
+        };
+        let provider = reth_db_provider(chain_spec, &datadir, self.static_files.as_deref())?;
+        let concurrency_level = self
+            .concurrency
+            .unwrap_or_else(|| thread::available_parallelism().unwrap_or(NonZeroUsize::MIN));
+        let builder_payment = match &self.builder_key_file {
+            Some(path) => Some(BuilderPayment::from_hex(
+                &read_key_file(path)?,
+                self.payment_margin,
+            )?),
+            None => None,
+        };
+        let build_options = BuildOptions {
+            builder_payment,
+            min_reverted_tx_fee: self.min_reverted_tx_fee,
+            ..Default::default()
+        };
+
+        match self.command {
+            Command::Build {
+                receipts_out,
+                report_out,
+                bundles,
+            } => {
+                let txs = eth_get_best_transactions(&self.rpc_url).await?.result;
+                let bundles = match bundles {
+                    Some(path) => load_bundles(&path)?,
+                    None => Vec::new(),
+                };
+                let built = run_pevm(
+                    &provider,
+                    self.beacon,
+                    txs,
+                    bundles,
+                    concurrency_level,
+                    &build_options,
+                )
+                .await?;
+                info!("built block: {:?}", built.block.header);
+                if let Some(path) = receipts_out {
+                    export_block_receipts(&path, &built.block, &built.senders, &built.receipts)?;
+                    info!("receipts written to {}", path.display());
+                }
+                info!(
+                    "block value to the proposer: {}",
+                    built.report.proposer_value()
+                );
+                if let Some(path) = report_out {
+                    export_build_report(&path, &built.report)?;
+                    info!("build report written to {}", path.display());
+                }
+            }
+            Command::Compare => {
+                let txs = eth_get_best_transactions(&self.rpc_url).await?.result;
+                let report =
+                    compare_execution(&provider, self.beacon, txs, concurrency_level).await?;
+                if let Some(mismatch) = report.first_mismatch {
+                    eyre::bail!("pevm and reth diverge at {}", mismatch);
+                }
+            }
+            Command::Replay { block } => {
+                if block == 0 {
+                    eyre::bail!("Genesis has no parent to replay on");
+                }
+                let canonical = provider
+                    .block_by_number(block)?
+                    .ok_or_else(|| eyre::eyre!("Block {} not found", block))?
+                    .seal_slow();
+                let parent = provider
+                    .sealed_header(block - 1)?
+                    .ok_or_else(|| eyre::eyre!("Parent of block {} not found", block))?;
+
+                let payload_attributes = PayloadAttributes {
+                    timestamp: canonical.timestamp,
+                    prev_randao: canonical.mix_hash,
+                    suggested_fee_recipient: canonical.beneficiary,
+                    withdrawals: canonical.withdrawals.clone().map(|w| w.into_inner()),
+                    parent_beacon_block_root: canonical.parent_beacon_block_root,
+                };
+                let built = build_block(
+                    &provider,
+                    &parent,
+                    &payload_attributes,
+                    recover_all_signers(canonical.body.clone())?,
+                    Vec::new(),
+                    concurrency_level,
+                    &BuildOptions {
+                        preserve_order: true,
+                        gas_limit: Some(canonical.gas_limit),
+                        extra_data: canonical.extra_data.clone(),
+                        ..Default::default()
+                    },
+                )?;
+
+                if !built.excluded.is_empty() || !built.rejected.is_empty() {
+                    eyre::bail!(
+                        "replay of block {} left {} txs out and rejected {}",
+                        block,
+                        built.excluded.len(),
+                        built.rejected.len()
+                    );
+                }
+
+                info!(
+                    "replayed block {}: state root {} (canonical {}), receipts root {} (canonical {}), gas used {} (canonical {})",
+                    block,
+                    built.block.state_root,
+                    canonical.state_root,
+                    built.block.receipts_root,
+                    canonical.receipts_root,
+                    built.block.gas_used,
+                    canonical.gas_used,
+                );
+                let mut differing = Vec::new();
+                if built.block.state_root != canonical.state_root {
+                    differing.push("state root");
+                }
+                if built.block.receipts_root != canonical.receipts_root {
+                    differing.push("receipts root");
+                }
+                if built.block.gas_used != canonical.gas_used {
+                    differing.push("gas used");
+                }
+                if !differing.is_empty() {
+                    eyre::bail!(
+                        "replayed block {} differs from canonical in {}",
+                        block,
+                        differing.join(", ")
+                    );
+                }
+                if built.block.hash() != canonical.hash() {
+                    eyre::bail!(
+                        "replayed block hash {} differs from canonical {}",
+                        built.block.hash(),
+                        canonical.hash()
+                    );
+                }
+            }
+            Command::Run {
+                rebuild_interval_ms,
+                engine_addr,
+                mut relay_urls,
+                builder_secret_key_file,
+                relay_encoding,
+                mock_relay_addr,
+                bundles,
+                bundle_rpc_addr,
+                mev_share_url,
+            } => {
+                let genesis_fork_version = || {
+                    self.genesis_fork_version
+                        .map(|version| version.0)
+                        .or_else(|| self.chain.genesis_fork_version())
+                        .ok_or_else(|| {
+                            eyre::eyre!(
+                                "--genesis-fork-version is required to sign bids for the custom chain {}",
+                                self.chain
+                            )
+                        })
+                };
+                if let Some(addr) = mock_relay_addr {
+                    let genesis_fork_version = genesis_fork_version()?;
+                    let addr = MockRelay::new(genesis_fork_version).start(addr).await?;
+                    relay_urls.push(format!("http://{}", addr));
+                }
+                let relay = match (builder_secret_key_file, relay_urls.is_empty()) {
+                    (Some(path), false) => {
+                        let signer = BuilderSigner::from_hex(
+                            &read_key_file(&path)?,
+                            genesis_fork_version()?,
+                        )?;
+                        Some(RelayClient::new(relay_urls, signer, relay_encoding))
+                    }
+                    (None, false) => {
+                        eyre::bail!("--builder-secret-key-file is required to submit to relays")
+                    }
+                    (_, true) => None,
+                };
+                let config = BuilderConfig {
+                    rpc_url: self.rpc_url,
+                    rebuild_interval: Duration::from_millis(rebuild_interval_ms),
+                    concurrency_level,
+                    relay,
+                    build_options,
+                };
+                let chain_spec = provider.chain_spec();
+                let builder = BlockBuilder::new(provider, self.beacon, config);
+                let orders = builder.orders();
+                if let Some(path) = bundles {
+                    let mut pool = orders.write().unwrap();
+                    for bundle in load_bundles(&path)? {
+                        pool.add_bundle(bundle);
+                    }
+                }
+                let _bundle_server = match bundle_rpc_addr {
+                    Some(addr) => Some(start_order_server(addr, orders.clone()).await?),
+                    None => None,
+                };
+                if let Some(endpoint) = mev_share_url {
+                    tokio::spawn(run_hint_stream(endpoint, orders));
+                }
+                let _engine_server = match engine_addr {
+                    Some(addr) => {
+                        Some(start_engine_server(addr, builder.payloads(), chain_spec).await?)
+                    }
+                    None => None,
+                };
+                builder.run().await?;
+            }
+            Command::Bench { iterations } => {
+                let txs = eth_get_best_transactions(&self.rpc_url).await?.result;
+                let txs = retain_chain_transactions(recover_signers(txs), &provider.chain_spec());
+                let payload_attributes = self.beacon.run().await?.data.payload_attributes;
+                let parent = provider
+                    .latest_header()?
+                    .ok_or_else(|| eyre::eyre!("Error fetching latest sealed header"))?;
+
+                let mut pevm_elapsed = Duration::ZERO;
+                let mut reth_elapsed = Duration::ZERO;
+                for _ in 0..iterations {
+                    pevm_elapsed += execute_pevm(
+                        &provider,
+                        &parent,
+                        &payload_attributes,
+                        &txs,
+                        concurrency_level,
+                    )?
+                    .elapsed;
+                    reth_elapsed +=
+                        execute_reth_with(&provider, &parent, &payload_attributes, txs.clone())?
+                            .elapsed;
+                }
+
+                let iterations = iterations.max(1);
+                let pevm_mean = pevm_elapsed / iterations;
+                let reth_mean = reth_elapsed / iterations;
+                info!(
+                    "{} txs, {} iterations: pevm {:?} ({} threads), reth {:?}, speedup {:.2}x",
+                    txs.len(),
+                    iterations,
+                    pevm_mean,
+                    concurrency_level,
+                    reth_mean,
+                    reth_mean.as_secs_f64() / pevm_mean.as_secs_f64().max(f64::EPSILON)
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Reads a hex encoded key from `path`, keys are not taken on the command line where
+/// other users can see them
+fn read_key_file(path: &Path) -> Result<String, PbbError> {
+    let key = std::fs::read_to_string(path).map_err(|source| PbbError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    Ok(key.trim().to_string())
+}
@@ -0,0 +1,630 @@
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::fmt;
+use std::num::NonZeroUsize;
+
+use log::info;
+use pevm::EvmAccount;
+use pevm::PevmTxExecutionResult;
+use reth_primitives::Address;
+use reth_primitives::Log;
+use reth_primitives::TransactionSigned;
+use reth_primitives::TransactionSignedEcRecovered;
+use reth_primitives::TxHash;
+use reth_primitives::B256;
+use reth_primitives::U256;
+use reth_provider::AccountReader;
+use reth_provider::BlockReaderIdExt;
+use reth_provider::ChainSpecProvider;
+use reth_provider::ProviderResult;
+use reth_provider::StateProviderFactory;
+use reth_revm::primitives::Account;
+use reth_revm::primitives::AccountInfo;
+use reth_revm::primitives::EvmState;
+use reth_revm::primitives::ExecutionResult;
+use reth_revm::primitives::KECCAK_EMPTY;
+
+use crate::error::PbbError;
+use crate::lighthouse::BeaconEventsConfig;
+use crate::pbb::execute_pevm;
+use crate::recovery::recover_signers;
+use crate::reth::execute_reth_with;
+use crate::reth::RethExecutionOutcome;
+use crate::reth_db::RethProvider;
+use crate::utils::retain_chain_transactions;
+
+/// Result of running the same transactions through pevm and the sequential reth executor
+#[derive(Debug, Clone)]
+pub struct ComparisonReport {
+    /// Number of transactions compared
+    pub transactions: usize,
+    /// First transaction on which both executors disagree, if any
+    pub first_mismatch: Option<Mismatch>,
+}
+
+impl ComparisonReport {
+    /// Returns true if both executors produced the same results
+    pub fn is_match(&self) -> bool {
+        self.first_mismatch.is_none()
+    }
+}
+
+/// Transaction on which pevm and reth disagree
+#[derive(Debug, Clone)]
+pub struct Mismatch {
+    pub index: usize,
+    pub tx_hash: TxHash,
+    pub difference: Difference,
+}
+
+/// What differs between the pevm and the reth execution of a transaction.
+///
+/// pevm receipts carry no return data, so outputs can't be compared directly;
+/// a differing output always shows up as a differing status, gas or state diff.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Difference {
+    /// reth failed to execute a transaction that pevm executed
+    RejectedByReth {
+        reason: String,
+    },
+    /// pevm failed to execute the block at this transaction
+    RejectedByPevm {
+        reason: String,
+    },
+    Status {
+        pevm: bool,
+        reth: bool,
+    },
+    GasUsed {
+        pevm: u64,
+        reth: u64,
+    },
+    Logs {
+        pevm: Vec<Log>,
+        reth: Vec<Log>,
+    },
+    /// reth changed an account pevm left untouched, from its state before the transaction
+    TouchedByRethOnly {
+        address: Address,
+        balance: U256,
+        nonce: u64,
+    },
+    /// The account exists after the transaction for one executor only
+    AccountExistence {
+        address: Address,
+        pevm: bool,
+        reth: bool,
+    },
+    Balance {
+        address: Address,
+        pevm: U256,
+        reth: U256,
+    },
+    Nonce {
+        address: Address,
+        pevm: u64,
+        reth: u64,
+    },
+    CodeHash {
+        address: Address,
+        pevm: B256,
+        reth: B256,
+    },
+    /// A storage slot was written by one executor only or to a different value
+    Storage {
+        address: Address,
+        slot: U256,
+        pevm: Option<U256>,
+        reth: Option<U256>,
+    },
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "tx #{} ({}): ", self.index, self.tx_hash)?;
+        match &self.difference {
+            Difference::RejectedByReth { reason } => write!(f, "rejected by reth: {reason}"),
+            Difference::RejectedByPevm { reason } => write!(f, "rejected by pevm: {reason}"),
+            Difference::Status { pevm, reth } => {
+                write!(f, "status differs, pevm success={pevm} reth success={reth}")
+            }
+            Difference::GasUsed { pevm, reth } => {
+                write!(f, "gas used differs, pevm {pevm} reth {reth}")
+            }
+            Difference::Logs { pevm, reth } => write!(
+                f,
+                "logs differ, pevm emitted {} reth emitted {}",
+                pevm.len(),
+                reth.len()
+            ),
+            Difference::TouchedByRethOnly {
+                address,
+                balance,
+                nonce,
+            } => write!(
+                f,
+                "{address} touched by reth only, with balance {balance} and nonce {nonce}"
+            ),
+            Difference::AccountExistence {
+                address,
+                pevm,
+                reth,
+            } => write!(
+                f,
+                "account {address} exists after execution: pevm {pevm} reth {reth}"
+            ),
+            Difference::Balance {
+                address,
+                pevm,
+                reth,
+            } => write!(f, "balance of {address} differs, pevm {pevm} reth {reth}"),
+            Difference::Nonce {
+                address,
+                pevm,
+                reth,
+            } => write!(f, "nonce of {address} differs, pevm {pevm} reth {reth}"),
+            Difference::CodeHash {
+                address,
+                pevm,
+                reth,
+            } => write!(f, "code hash of {address} differs, pevm {pevm} reth {reth}"),
+            Difference::Storage {
+                address,
+                slot,
+                pevm,
+                reth,
+            } => write!(
+                f,
+                "storage slot {slot} of {address} differs, pevm {pevm:?} reth {reth:?}"
+            ),
+        }
+    }
+}
+
+/// Runs `txs` through both executors against the same parent state and payload attributes
+pub async fn compare_execution(
+    provider: &RethProvider,
+    beacon_client: BeaconEventsConfig,
+    txs: Vec<TransactionSigned>,
+    concurrency_level: NonZeroUsize,
+) -> Result<ComparisonReport, PbbError> {
+    let payload_attributes = beacon_client.run().await?.data.payload_attributes;
+
+    let latest_block_header = provider
+        .latest_header()?
+        .ok_or(PbbError::MissingLatestHeader)?;
+
+    let txs = retain_chain_transactions(recover_signers(txs), &provider.chain_spec());
+    let (pevm_results, pevm_error) = execute_longest_prefix(&txs, |txs| {
+        execute_pevm(
+            provider,
+            &latest_block_header,
+            &payload_attributes,
+            txs,
+            concurrency_level,
+        )
+        .map(|execution| execution.results)
+    });
+    let reth = execute_reth_with(
+        provider,
+        &latest_block_header,
+        &payload_attributes,
+        txs.clone(),
+    )?;
+
+    let parent_state = provider.state_by_block_hash(latest_block_header.hash())?;
+    let report = compare_results(
+        &txs,
+        &pevm_results,
+        pevm_error.as_ref(),
+        &reth,
+        &parent_state,
+    )?;
+    match &report.first_mismatch {
+        Some(mismatch) => info!("pevm and reth diverge at {}", mismatch),
+        None => info!("pevm and reth agree on all {} txs", report.transactions),
+    }
+    Ok(report)
+}
+
+/// Executes `txs` with `execute`, and when that fails the longest prefix of them it
+/// executes, found by bisection.
+///
+/// pevm fails the whole block on a single invalid transaction without telling which, the
+/// results cover the transactions before it and the error is the one pevm fails with
+/// once it is included.
+fn execute_longest_prefix<T>(
+    txs: &[TransactionSignedEcRecovered],
+    mut execute: impl FnMut(&[TransactionSignedEcRecovered]) -> Result<Vec<T>, PbbError>,
+) -> (Vec<T>, Option<PbbError>) {
+    let mut error = match execute(txs) {
+        Ok(results) => return (results, None),
+        Err(e) => e,
+    };
+    // the prefix of length `valid` executes, the one of length `invalid` doesn't
+    let (mut valid, mut invalid) = (0, txs.len());
+    let mut results = Vec::new();
+    while invalid - valid > 1 {
+        let len = (valid + invalid) / 2;
+        match execute(&txs[..len]) {
+            Ok(prefix_results) => {
+                valid = len;
+                results = prefix_results;
+            }
+            Err(e) => {
+                invalid = len;
+                error = e;
+            }
+        }
+    }
+    (results, Some(error))
+}
+
+/// Compares the pevm results of `txs` against the reth execution of the same list on
+/// top of `parent_state`.
+///
+/// `pevm` may only cover a prefix of `txs` when pevm failed with `pevm_error` on the
+/// transaction after it.
+pub fn compare_results(
+    txs: &[TransactionSignedEcRecovered],
+    pevm: &[PevmTxExecutionResult],
+    pevm_error: Option<&PbbError>,
+    reth: &RethExecutionOutcome,
+    parent_state: &impl AccountReader,
+) -> ProviderResult<ComparisonReport> {
+    let mut reth_index = 0;
+    let mut previous_cumulative_gas_used = 0;
+    // accounts changed by the transactions reth executed so far
+    let mut changed: HashMap<Address, AccountInfo> = HashMap::new();
+
+    for (index, (tx, pevm_result)) in txs.iter().zip(pevm).enumerate() {
+        let tx_hash = tx.hash();
+        let mismatch = |difference| ComparisonReport {
+            transactions: txs.len(),
+            first_mismatch: Some(Mismatch {
+                index,
+                tx_hash,
+                difference,
+            }),
+        };
+
+        // reth skips the transactions it fails to execute
+        if reth.transactions.get(reth_index).map(|tx| tx.hash()) != Some(tx_hash) {
+            let reason = reth
+                .rejected
+                .iter()
+                .find(|rejected| rejected.hash == tx_hash)
+                .map(|rejected| rejected.error.to_string())
+                .unwrap_or_else(|| String::from("not executed"));
+            return Ok(mismatch(Difference::RejectedByReth { reason }));
+        }
+
+        let cumulative_gas_used = pevm_result.receipt.cumulative_gas_used as u64;
+        let pevm_gas_used = cumulative_gas_used - previous_cumulative_gas_used;
+        previous_cumulative_gas_used = cumulative_gas_used;
+
+        // pevm only reports the accounts it changed, the state before the transaction
+        // tells whether the other accounts reth reports changed
+        let reth_state = &reth.states[reth_index];
+        let mut before = HashMap::new();
+        for address in reth_state.keys() {
+            if pevm_result.state.contains_key(address) {
+                continue;
+            }
+            let info = match changed.get(address) {
+                Some(info) => info.clone(),
+                None => account_info(parent_state.basic_account(*address)?),
+            };
+            before.insert(*address, info);
+        }
+
+        let difference = compare_tx(
+            pevm_result,
+            pevm_gas_used,
+            &reth.results[reth_index],
+            reth_state,
+            &before,
+        );
+        if let Some(difference) = difference {
+            return Ok(mismatch(difference));
+        }
+        for (address, account) in reth_state {
+            if account.is_selfdestructed() {
+                changed.insert(*address, AccountInfo::default());
+            } else if account.is_touched() {
+                changed.insert(*address, account_info_without_code(&account.info));
+            }
+        }
+        reth_index += 1;
+    }
+
+    let first_mismatch = pevm_error
+        .zip(txs.get(pevm.len()))
+        .map(|(error, tx)| Mismatch {
+            index: pevm.len(),
+            tx_hash: tx.hash(),
+            difference: Difference::RejectedByPevm {
+                reason: error.to_string(),
+            },
+        });
+    Ok(ComparisonReport {
+        transactions: txs.len(),
+        first_mismatch,
+    })
+}
+
+/// Returns the account info of a stored account, an empty one if it doesn't exist
+fn account_info(account: Option<reth_primitives::Account>) -> AccountInfo {
+    account.map_or_else(AccountInfo::default, |account| AccountInfo {
+        balance: account.balance,
+        nonce: account.nonce,
+        code_hash: account.bytecode_hash.unwrap_or(KECCAK_EMPTY),
+        code: None,
+    })
+}
+
+/// Returns `info` without its code, only its hash is compared
+fn account_info_without_code(info: &AccountInfo) -> AccountInfo {
+    AccountInfo {
+        balance: info.balance,
+        nonce: info.nonce,
+        code_hash: info.code_hash,
+        code: None,
+    }
+}
+
+fn compare_tx(
+    pevm: &PevmTxExecutionResult,
+    pevm_gas_used: u64,
+    reth: &ExecutionResult,
+    reth_state: &EvmState,
+    before: &HashMap<Address, AccountInfo>,
+) -> Option<Difference> {
+    let pevm_success = pevm.receipt.status.coerce_status();
+    if pevm_success != reth.is_success() {
+        return Some(Difference::Status {
+            pevm: pevm_success,
+            reth: reth.is_success(),
+        });
+    }
+    if pevm_gas_used != reth.gas_used() {
+        return Some(Difference::GasUsed {
+            pevm: pevm_gas_used,
+            reth: reth.gas_used(),
+        });
+    }
+    if pevm.receipt.logs != reth.logs() {
+        return Some(Difference::Logs {
+            pevm: pevm.receipt.logs.clone(),
+            reth: reth.logs().to_vec(),
+        });
+    }
+
+    let addresses: BTreeSet<Address> = pevm
+        .state
+        .keys()
+        .chain(reth_state.keys())
+        .copied()
+        .collect();
+    addresses.into_iter().find_map(|address| {
+        compare_account(
+            address,
+            pevm.state.get(&address),
+            reth_state.get(&address),
+            before.get(&address),
+        )
+    })
+}
+
+/// Compares the account at `address` after the transaction, `before` holding its state
+/// before the transaction when pevm doesn't report it
+fn compare_account(
+    address: Address,
+    pevm: Option<&Option<EvmAccount>>,
+    reth: Option<&Account>,
+    before: Option<&AccountInfo>,
+) -> Option<Difference> {
+    let (pevm, reth) = match (pevm, reth) {
+        (Some(pevm), Some(reth)) => (pevm.as_ref(), reth),
+        // reth reports every account it loaded and touches the ones that are only
+        // called, pevm only the accounts it changed, so an account reth alone reports is
+        // a difference only if reth changed it
+        (None, Some(reth)) => {
+            return changed_by_reth(reth, before).then_some(Difference::TouchedByRethOnly {
+                address,
+                balance: reth.info.balance,
+                nonce: reth.info.nonce,
+            })
+        }
+        (Some(pevm), None) => {
+            return Some(Difference::AccountExistence {
+                address,
+                pevm: pevm.is_some(),
+                reth: false,
+            })
+        }
+        (None, None) => return None,
+    };
+
+    let Some(pevm) = pevm else {
+        return (!reth.is_selfdestructed()).then_some(Difference::AccountExistence {
+            address,
+            pevm: false,
+            reth: true,
+        });
+    };
+    if reth.is_selfdestructed() {
+        return Some(Difference::AccountExistence {
+            address,
+            pevm: true,
+            reth: false,
+        });
+    }
+
+    if pevm.basic.balance != reth.info.balance {
+        return Some(Difference::Balance {
+            address,
+            pevm: pevm.basic.balance,
+            reth: reth.info.balance,
+        });
+    }
+    if pevm.basic.nonce != reth.info.nonce {
+        return Some(Difference::Nonce {
+            address,
+            pevm: pevm.basic.nonce,
+            reth: reth.info.nonce,
+        });
+    }
+    let pevm_code_hash = pevm.basic.code_hash.unwrap_or(KECCAK_EMPTY);
+    if pevm_code_hash != reth.info.code_hash {
+        return Some(Difference::CodeHash {
+            address,
+            pevm: pevm_code_hash,
+            reth: reth.info.code_hash,
+        });
+    }
+
+    let slots: BTreeSet<U256> = pevm
+        .storage
+        .keys()
+        .copied()
+        .chain(
+            reth.storage
+                .iter()
+                .filter(|(_, slot)| slot.is_changed())
+                .map(|(slot, _)| *slot),
+        )
+        .collect();
+    slots.into_iter().find_map(|slot| {
+        let pevm_value = pevm.storage.get(&slot).copied();
+        let reth_value = reth.storage.get(&slot).map(|value| value.present_value);
+        (pevm_value != reth_value).then_some(Difference::Storage {
+            address,
+            slot,
+            pevm: pevm_value,
+            reth: reth_value,
+        })
+    })
+}
+
+/// Returns true if reth changed the account from its state `before` the transaction
+fn changed_by_reth(reth: &Account, before: Option<&AccountInfo>) -> bool {
+    if !reth.is_touched() {
+        return false;
+    }
+    let before = before.cloned().unwrap_or_default();
+    let info_changed = if reth.is_selfdestructed() {
+        before.balance != U256::ZERO || before.nonce != 0 || before.code_hash != KECCAK_EMPTY
+    } else {
+        reth.info.balance != before.balance
+            || reth.info.nonce != before.nonce
+            || reth.info.code_hash != before.code_hash
+    };
+    info_changed || reth.storage.values().any(|slot| slot.is_changed())
+}
+
+#[cfg(test)]
+mod tests {
+    use reth_revm::primitives::EvmStorageSlot;
+
+    use super::*;
+    use crate::test_utils::tx;
+    use crate::test_utils::MockAccounts;
+    use crate::test_utils::TRANSFER_GAS;
+
+    const ALICE: Address = Address::repeat_byte(0xa1);
+
+    fn info(balance: u64, nonce: u64) -> AccountInfo {
+        AccountInfo {
+            balance: U256::from(balance),
+            nonce,
+            code_hash: KECCAK_EMPTY,
+            code: None,
+        }
+    }
+
+    fn touched(info: AccountInfo) -> Account {
+        let mut account = Account::from(info);
+        account.mark_touch();
+        account
+    }
+
+    #[test]
+    fn ignores_accounts_reth_touched_without_changing_them() {
+        // a zero value call touches the callee without changing it
+        let reth = touched(info(100, 1));
+        assert_eq!(
+            compare_account(ALICE, None, Some(&reth), Some(&info(100, 1))),
+            None
+        );
+        // an account that is only read is not touched
+        let loaded = Account::from(info(5, 0));
+        assert_eq!(compare_account(ALICE, None, Some(&loaded), None), None);
+    }
+
+    #[test]
+    fn reports_accounts_only_reth_changed() {
+        let reth = touched(info(150, 1));
+        assert_eq!(
+            compare_account(ALICE, None, Some(&reth), Some(&info(100, 1))),
+            Some(Difference::TouchedByRethOnly {
+                address: ALICE,
+                balance: U256::from(150),
+                nonce: 1,
+            })
+        );
+
+        let mut written = touched(info(100, 1));
+        written.storage.insert(
+            U256::from(1),
+            EvmStorageSlot::new_changed(U256::ZERO, U256::from(7)),
+        );
+        assert!(compare_account(ALICE, None, Some(&written), Some(&info(100, 1))).is_some());
+    }
+
+    #[test]
+    fn accounts_missing_before_are_empty() {
+        // an empty account created by a zero value call is removed again
+        let reth = touched(AccountInfo::default());
+        assert_eq!(compare_account(ALICE, None, Some(&reth), None), None);
+        assert_eq!(
+            account_info(None).code_hash,
+            AccountInfo::default().code_hash
+        );
+    }
+
+    #[test]
+    fn reports_the_transaction_pevm_fails_on() {
+        // pevm fails the whole block on the nonce gap of the second transaction
+        let txs: Vec<TransactionSignedEcRecovered> = [0, 2, 3]
+            .map(|nonce| tx(ALICE, nonce, TRANSFER_GAS, 100, 1))
+            .into();
+        let invalid = txs[1].hash();
+        let (results, error) = execute_longest_prefix(&txs, |txs| {
+            if txs.iter().any(|tx| tx.hash() == invalid) {
+                return Err(PbbError::Execution("nonce too high".into()));
+            }
+            Ok(txs.iter().map(|tx| tx.hash()).collect())
+        });
+        assert_eq!(results, [txs[0].hash()]);
+        assert!(error.is_some());
+
+        // compared on its own, the comparison stops on it instead of failing
+        let report = compare_results(
+            &txs[1..],
+            &[],
+            error.as_ref(),
+            &RethExecutionOutcome::default(),
+            &MockAccounts::default(),
+        )
+        .unwrap();
+        let mismatch = report.first_mismatch.unwrap();
+        assert_eq!((mismatch.index, mismatch.tx_hash), (0, invalid));
+        assert_eq!(
+            mismatch.difference,
+            Difference::RejectedByPevm {
+                reason: String::from("execution failed: nonce too high"),
+            }
+        );
+    }
+}
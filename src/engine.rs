@@ -0,0 +1,234 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use alloy_rlp::Encodable;
+use jsonrpsee::core::async_trait;
+use jsonrpsee::core::RpcResult;
+use jsonrpsee::proc_macros::rpc;
+use jsonrpsee::server::Server;
+use jsonrpsee::server::ServerHandle;
+use jsonrpsee::types::ErrorObjectOwned;
+use log::info;
+use reth_chainspec::ChainSpec;
+use reth_primitives::B256;
+use reth_rpc_types::engine::ExecutionPayloadEnvelopeV3;
+use reth_rpc_types::engine::ExecutionPayloadEnvelopeV4;
+use reth_rpc_types::engine::PayloadAttributes;
+use reth_rpc_types::engine::PayloadId;
+use reth_rpc_types_compat::engine::payload::block_to_payload_v3;
+use reth_rpc_types_compat::engine::payload::block_to_payload_v4;
+use sha2::Digest;
+use sha2::Sha256;
+
+use crate::builder::PayloadStore;
+use crate::builder::SlotPayload;
+use crate::error::PbbError;
+
+/// Engine API error code returned for a payload id the builder does not know
+const UNKNOWN_PAYLOAD_CODE: i32 = -38001;
+
+/// Engine API error code returned when the payload is of another fork than the method
+const UNSUPPORTED_FORK_CODE: i32 = -38005;
+
+/// Derives the payload id of a build job with the formula of reth: the first 8 bytes of
+/// the sha256 of the parent hash, then the timestamp, prev randao, fee recipient, RLP
+/// encoded withdrawals and parent beacon block root of the attributes. A reth node
+/// returns the same id from `engine_forkchoiceUpdated` for these attributes
+pub fn payload_id(parent: &B256, attributes: &PayloadAttributes) -> PayloadId {
+    let mut hasher = Sha256::new();
+    hasher.update(parent.as_slice());
+    hasher.update(attributes.timestamp.to_be_bytes());
+    hasher.update(attributes.prev_randao.as_slice());
+    hasher.update(attributes.suggested_fee_recipient.as_slice());
+    if let Some(withdrawals) = &attributes.withdrawals {
+        let mut buf = Vec::new();
+        withdrawals.encode(&mut buf);
+        hasher.update(buf);
+    }
+    if let Some(parent_beacon_block_root) = attributes.parent_beacon_block_root {
+        hasher.update(parent_beacon_block_root.as_slice());
+    }
+    let out = hasher.finalize();
+    PayloadId::new(out[..8].try_into().expect("sha256 output is 32 bytes"))
+}
+
+/// Subset of the Engine API that serves the payloads built by the builder
+#[rpc(server, namespace = "engine")]
+pub trait EngineGetPayloadApi {
+    #[method(name = "getPayloadV3")]
+    async fn get_payload_v3(&self, payload_id: PayloadId) -> RpcResult<ExecutionPayloadEnvelopeV3>;
+
+    #[method(name = "getPayloadV4")]
+    async fn get_payload_v4(&self, payload_id: PayloadId) -> RpcResult<ExecutionPayloadEnvelopeV4>;
+}
+
+/// Serves the best payload of every build job by its payload id.
+///
+/// The server does not authenticate requests, it is meant for a local consensus
+/// client or a test harness only.
+pub struct EngineGetPayload {
+    payloads: PayloadStore,
+    chain_spec: Arc<ChainSpec>,
+}
+
+impl EngineGetPayload {
+    /// Creates a new handler over the payloads of the builder
+    pub fn new(payloads: PayloadStore, chain_spec: Arc<ChainSpec>) -> Self {
+        Self {
+            payloads,
+            chain_spec,
+        }
+    }
+
+    /// Returns the payload of `payload_id` if `fork_active` holds at its timestamp, the
+    /// way reth checks that the method version matches the fork of the payload
+    fn payload(
+        &self,
+        payload_id: PayloadId,
+        fork_active: fn(&ChainSpec, u64) -> bool,
+    ) -> RpcResult<SlotPayload> {
+        let payload = self
+            .payloads
+            .read()
+            .unwrap()
+            .get(&payload_id)
+            .cloned()
+            .ok_or_else(|| {
+                ErrorObjectOwned::owned(UNKNOWN_PAYLOAD_CODE, "Unknown payload", None::<()>)
+            })?;
+        if !fork_active(&self.chain_spec, payload.built.block.timestamp) {
+            return Err(ErrorObjectOwned::owned(
+                UNSUPPORTED_FORK_CODE,
+                "Unsupported fork",
+                None::<()>,
+            ));
+        }
+        Ok(payload)
+    }
+}
+
+#[async_trait]
+impl EngineGetPayloadApiServer for EngineGetPayload {
+    async fn get_payload_v3(&self, payload_id: PayloadId) -> RpcResult<ExecutionPayloadEnvelopeV3> {
+        let payload = self.payload(payload_id, ChainSpec::is_cancun_active_at_timestamp)?;
+        Ok(ExecutionPayloadEnvelopeV3 {
+            execution_payload: block_to_payload_v3(payload.built.block),
+            block_value: payload.value,
+            blobs_bundle: payload.blobs_bundle,
+            should_override_builder: false,
+        })
+    }
+
+    async fn get_payload_v4(&self, payload_id: PayloadId) -> RpcResult<ExecutionPayloadEnvelopeV4> {
+        // pevm can't execute Prague blocks yet, so this only ever rejects payloads
+        let payload = self.payload(payload_id, ChainSpec::is_prague_active_at_timestamp)?;
+        Ok(ExecutionPayloadEnvelopeV4 {
+            execution_payload: block_to_payload_v4(payload.built.block),
+            block_value: payload.value,
+            blobs_bundle: payload.blobs_bundle,
+            should_override_builder: false,
+        })
+    }
+}
+
+/// Starts the payload server on `addr`
+pub async fn start_engine_server(
+    addr: SocketAddr,
+    payloads: PayloadStore,
+    chain_spec: Arc<ChainSpec>,
+) -> Result<ServerHandle, PbbError> {
+    let server_error = |source| PbbError::Server { addr, source };
+    let server = Server::builder().build(addr).await.map_err(server_error)?;
+    let addr = server.local_addr().map_err(server_error)?;
+    let handle = server.start(EngineGetPayload::new(payloads, chain_spec).into_rpc());
+    info!("Engine API payload server listening on {}", addr);
+    Ok(handle)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::RwLock;
+
+    use reth_chainspec::MAINNET;
+    use reth_primitives::Block;
+    use reth_primitives::Header;
+    use reth_primitives::U256;
+    use reth_rpc_types::engine::BlobsBundleV1;
+
+    use super::*;
+    use crate::block::BuiltBlock;
+    use crate::report::BlockValue;
+    use crate::report::BuildReport;
+
+    /// Timestamps of the first mainnet blocks of Shanghai and Cancun
+    const SHANGHAI_TIMESTAMP: u64 = 1_681_338_455;
+    const CANCUN_TIMESTAMP: u64 = 1_710_338_135;
+
+    fn payload_id() -> PayloadId {
+        PayloadId::new([1; 8])
+    }
+
+    /// Handler serving an empty mainnet payload built for `timestamp`
+    fn handler(timestamp: u64) -> EngineGetPayload {
+        let block = Block {
+            header: Header {
+                timestamp,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+        .seal_slow();
+        let payload = SlotPayload {
+            slot: 32,
+            value: U256::ZERO,
+            blobs_bundle: BlobsBundleV1 {
+                commitments: Vec::new(),
+                proofs: Vec::new(),
+                blobs: Vec::new(),
+            },
+            built: BuiltBlock {
+                report: BuildReport {
+                    block_number: block.number,
+                    coinbase: block.beneficiary,
+                    proposer_payment: None,
+                    total: BlockValue::default(),
+                    txs: Vec::new(),
+                    bundles: Vec::new(),
+                },
+                block,
+                senders: Vec::new(),
+                receipts: Vec::new(),
+                bundles: Vec::new(),
+                excluded: Vec::new(),
+                rejected: Vec::new(),
+                excluded_bundles: Vec::new(),
+            },
+        };
+        let payloads = Arc::new(RwLock::new(HashMap::from([(payload_id(), payload)])));
+        EngineGetPayload::new(payloads, MAINNET.clone())
+    }
+
+    #[tokio::test]
+    async fn get_payload_v3_rejects_pre_cancun_payloads() {
+        let error = handler(SHANGHAI_TIMESTAMP)
+            .get_payload_v3(payload_id())
+            .await
+            .unwrap_err();
+        assert_eq!(error.code(), UNSUPPORTED_FORK_CODE);
+
+        assert!(handler(CANCUN_TIMESTAMP)
+            .get_payload_v3(payload_id())
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn get_payload_v4_rejects_pre_prague_payloads() {
+        let error = handler(CANCUN_TIMESTAMP)
+            .get_payload_v4(payload_id())
+            .await
+            .unwrap_err();
+        assert_eq!(error.code(), UNSUPPORTED_FORK_CODE);
+    }
+}
@@ -0,0 +1,321 @@
+use std::fmt;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+use blst::BLST_ERROR;
+use mev_share_sse::client::SseError;
+use pevm::PevmError;
+use reth_primitives::hex::FromHexError;
+use reth_primitives::BlobTransactionValidationError;
+use reth_primitives::TxHash;
+use reth_primitives::B256;
+use reth_primitives::U256;
+use reth_provider::ProviderError;
+
+use crate::mock_relay::SubmissionError;
+use crate::utils::UnsupportedSpecError;
+
+/// Error of a dependency that has no error type of its own or whose error type isn't
+/// exported
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Errors of the block builder
+#[derive(Debug)]
+pub enum PbbError {
+    /// The datadir has no reth database
+    MissingDatabase { path: PathBuf },
+    /// The reth database can't be opened
+    OpenDatabase { path: PathBuf, source: BoxError },
+    /// The datadir has no reth static files
+    MissingStaticFiles { path: PathBuf },
+    /// The reth static files can't be opened
+    OpenStaticFiles {
+        path: PathBuf,
+        source: ProviderError,
+    },
+    /// The blockchain tree over the reth database can't be initialized
+    BlockchainTree(BoxError),
+    /// Reading the state or the chain from the reth database failed
+    Database(ProviderError),
+    /// The platform has no data directory to find the reth datadir in
+    MissingDataDir,
+    /// The genesis file of a custom network can't be read or parsed
+    ChainSpec { path: PathBuf, reason: String },
+    /// The latest block is missing from the database
+    MissingLatestHeader,
+    /// The beacon node event stream failed
+    Beacon(SseError),
+    /// The beacon node event stream ended without an event
+    BeaconStreamEnded,
+    /// A request to the beacon node API failed
+    BeaconApi(reqwest::Error),
+    /// A request to the execution client RPC failed
+    Rpc(reqwest::Error),
+    /// A file can't be read or written
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    /// A value can't be encoded to or decoded from JSON
+    Json(serde_json::Error),
+    /// A server can't listen on its address
+    Server {
+        addr: SocketAddr,
+        source: std::io::Error,
+    },
+    /// A transaction isn't a valid EIP-2718 encoded signed transaction
+    TransactionEncoding(alloy_rlp::Error),
+    /// The signer of a transaction can't be recovered from its signature
+    SignatureRecovery { tx_hash: TxHash },
+    /// The code of an account is in a format pevm can't execute, like EOF
+    UnsupportedBytecode { code_hash: B256 },
+    /// The spec of the block isn't supported by pevm
+    UnsupportedSpec(UnsupportedSpecError),
+    /// Executing the system calls of a block failed
+    Execution(BoxError),
+    /// pevm failed to execute the transactions of a block
+    Pevm(PevmError),
+    /// The block is built on a parent that isn't in the canonical chain of the database
+    NonCanonicalParent { number: u64, hash: B256 },
+    /// A field of the block environment doesn't fit in the block header
+    HeaderOverflow { field: &'static str, value: U256 },
+    /// Computing the state root of the block failed
+    StateRoot(BoxError),
+    /// The coinbase profit does not cover the builder margin and the payment gas
+    InsufficientProfit {
+        profit: U256,
+        margin: U256,
+        payment_gas_cost: U256,
+    },
+    /// Signing the payment to the proposer failed
+    PaymentSigning(secp256k1::Error),
+    /// A key isn't valid hex
+    Hex(FromHexError),
+    /// The builder secp256k1 secret key is invalid
+    InvalidSecretKey(secp256k1::Error),
+    /// A BLS key is invalid
+    InvalidBlsKey(BLST_ERROR),
+    /// A bid signature is malformed or does not verify
+    InvalidBidSignature(BLST_ERROR),
+    /// A bundle has no transactions
+    EmptyBundle,
+    /// A `mev_sendBundle` body doesn't start with the hash of a hint
+    MissingHint,
+    /// A `mev_sendBundle` body references more than one hint
+    MultipleHints,
+    /// A backrun references a hint that is unknown or expired
+    UnknownHint { hash: B256 },
+    /// A backrun references a hint whose transaction isn't in the public mempool
+    PrivateHint { hash: B256 },
+    /// A backrun only targets blocks before the block being built
+    PastBackrun { max_block: u64, block_number: u64 },
+    /// The node returned another number of blob sidecars than requested
+    BlobSidecarCount { requested: usize, received: usize },
+    /// The sidecar of a blob transaction of the block isn't known
+    MissingBlobSidecar { tx_hash: TxHash },
+    /// A sidecar was checked against a transaction that carries no blobs
+    NotBlobTransaction { tx_hash: TxHash },
+    /// A sidecar does not match the versioned hashes of its transaction
+    InvalidBlobSidecar(BlobTransactionValidationError),
+    /// The relay submission encoding is neither json nor ssz
+    UnknownSubmissionEncoding(String),
+    /// A relay can't be reached
+    RelayRequest {
+        relay_url: String,
+        source: reqwest::Error,
+    },
+    /// A relay rejected a block submission
+    RelayRejected {
+        relay_url: String,
+        status: reqwest::StatusCode,
+        reason: String,
+    },
+    /// The mock relay rejected a block submission
+    InvalidSubmission(SubmissionError),
+}
+
+impl fmt::Display for PbbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingDatabase { path } => {
+                write!(f, "no reth database found at {}", path.display())
+            }
+            Self::OpenDatabase { path, source } => write!(
+                f,
+                "failed to open the MDBX env at {}, it may be locked or written by an incompatible reth version: {source}",
+                path.display()
+            ),
+            Self::MissingStaticFiles { path } => {
+                write!(f, "no reth static files found at {}", path.display())
+            }
+            Self::OpenStaticFiles { path, source } => write!(
+                f,
+                "failed to open the static files at {}: {source}",
+                path.display()
+            ),
+            Self::BlockchainTree(e) => write!(f, "failed to initialize the blockchain tree: {e}"),
+            Self::Database(e) => write!(f, "database error: {e}"),
+            Self::MissingDataDir => write!(f, "could not resolve the platform data directory"),
+            Self::ChainSpec { path, reason } => {
+                write!(f, "failed to load the genesis file {}: {reason}", path.display())
+            }
+            Self::MissingLatestHeader => write!(f, "latest sealed header not found"),
+            Self::Beacon(e) => write!(f, "beacon node event stream failed: {e}"),
+            Self::BeaconStreamEnded => write!(f, "beacon node event stream ended"),
+            Self::BeaconApi(e) => write!(f, "beacon node API request failed: {e}"),
+            Self::Rpc(e) => write!(f, "execution client RPC request failed: {e}"),
+            Self::Io { path, source } => write!(f, "failed to access {}: {source}", path.display()),
+            Self::Json(e) => write!(f, "JSON error: {e}"),
+            Self::Server { addr, source } => {
+                write!(f, "failed to start the server on {addr}: {source}")
+            }
+            Self::TransactionEncoding(e) => write!(f, "invalid transaction encoding: {e}"),
+            Self::SignatureRecovery { tx_hash } => {
+                write!(f, "can't recover the signer of tx {tx_hash}")
+            }
+            Self::UnsupportedBytecode { code_hash } => {
+                write!(f, "bytecode {code_hash} is not supported by pevm")
+            }
+            Self::UnsupportedSpec(e) => e.fmt(f),
+            Self::Execution(e) => write!(f, "execution failed: {e}"),
+            Self::Pevm(e) => write!(f, "pevm execution failed: {e:?}"),
+            Self::NonCanonicalParent { number, hash } => write!(
+                f,
+                "parent block {number} ({hash}) is not in the canonical chain of the database"
+            ),
+            Self::HeaderOverflow { field, value } => {
+                write!(f, "{field} {value} does not fit in a block header")
+            }
+            Self::StateRoot(e) => write!(f, "state root computation failed: {e}"),
+            Self::InsufficientProfit {
+                profit,
+                margin,
+                payment_gas_cost,
+            } => write!(
+                f,
+                "coinbase profit {profit} does not cover the margin {margin} and the payment gas {payment_gas_cost}"
+            ),
+            Self::PaymentSigning(e) => write!(f, "failed to sign the proposer payment: {e}"),
+            Self::Hex(e) => write!(f, "invalid hex: {e}"),
+            Self::InvalidSecretKey(e) => write!(f, "invalid builder secret key: {e}"),
+            Self::InvalidBlsKey(e) => write!(f, "invalid BLS key: {e:?}"),
+            Self::InvalidBidSignature(e) => write!(f, "bid signature does not verify: {e:?}"),
+            Self::EmptyBundle => write!(f, "bundle has no transactions"),
+            Self::MissingHint => write!(f, "a backrun bundle must start with the hash of a hint"),
+            Self::MultipleHints => write!(f, "a backrun bundle may only reference a single hint"),
+            Self::UnknownHint { hash } => write!(f, "unknown or expired MEV-Share hint {hash}"),
+            Self::PrivateHint { hash } => write!(
+                f,
+                "transaction of MEV-Share hint {hash} is not in the public mempool, private transactions can't be backrun"
+            ),
+            Self::PastBackrun {
+                max_block,
+                block_number,
+            } => write!(
+                f,
+                "backrun bundle targets blocks up to {max_block}, block {block_number} is being built"
+            ),
+            Self::BlobSidecarCount {
+                requested,
+                received,
+            } => write!(f, "requested {requested} blob sidecars, got {received}"),
+            Self::MissingBlobSidecar { tx_hash } => {
+                write!(f, "missing blob sidecar for tx {tx_hash}")
+            }
+            Self::NotBlobTransaction { tx_hash } => write!(f, "tx {tx_hash} carries no blobs"),
+            Self::InvalidBlobSidecar(e) => write!(f, "invalid blob sidecar: {e}"),
+            Self::UnknownSubmissionEncoding(encoding) => write!(
+                f,
+                "unknown submission encoding {encoding}, expected json or ssz"
+            ),
+            Self::RelayRequest { relay_url, source } => {
+                write!(f, "error submitting to relay {relay_url}: {source}")
+            }
+            Self::RelayRejected {
+                relay_url,
+                status,
+                reason,
+            } => write!(
+                f,
+                "relay {relay_url} rejected the submission with {status}: {reason}"
+            ),
+            Self::InvalidSubmission(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for PbbError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::OpenDatabase { source, .. } => Some(source.as_ref()),
+            Self::OpenStaticFiles { source, .. } => Some(source),
+            Self::BlockchainTree(e) => Some(e.as_ref()),
+            Self::Database(e) => Some(e),
+            Self::Beacon(e) => Some(e),
+            Self::BeaconApi(e) => Some(e),
+            Self::Rpc(e) => Some(e),
+            Self::Io { source, .. } => Some(source),
+            Self::Json(e) => Some(e),
+            Self::Server { source, .. } => Some(source),
+            Self::TransactionEncoding(e) => Some(e),
+            Self::UnsupportedSpec(e) => Some(e),
+            Self::Execution(e) => Some(e.as_ref()),
+            Self::StateRoot(e) => Some(e.as_ref()),
+            Self::PaymentSigning(e) => Some(e),
+            Self::Hex(e) => Some(e),
+            Self::InvalidSecretKey(e) => Some(e),
+            Self::InvalidBlobSidecar(e) => Some(e),
+            Self::RelayRequest { source, .. } => Some(source),
+            Self::InvalidSubmission(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<ProviderError> for PbbError {
+    fn from(e: ProviderError) -> Self {
+        Self::Database(e)
+    }
+}
+
+impl From<SseError> for PbbError {
+    fn from(e: SseError) -> Self {
+        Self::Beacon(e)
+    }
+}
+
+impl From<reqwest::Error> for PbbError {
+    fn from(e: reqwest::Error) -> Self {
+        Self::Rpc(e)
+    }
+}
+
+impl From<UnsupportedSpecError> for PbbError {
+    fn from(e: UnsupportedSpecError) -> Self {
+        Self::UnsupportedSpec(e)
+    }
+}
+
+impl From<serde_json::Error> for PbbError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Json(e)
+    }
+}
+
+impl From<alloy_rlp::Error> for PbbError {
+    fn from(e: alloy_rlp::Error) -> Self {
+        Self::TransactionEncoding(e)
+    }
+}
+
+impl From<FromHexError> for PbbError {
+    fn from(e: FromHexError) -> Self {
+        Self::Hex(e)
+    }
+}
+
+impl From<SubmissionError> for PbbError {
+    fn from(e: SubmissionError) -> Self {
+        Self::InvalidSubmission(e)
+    }
+}
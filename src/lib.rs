@@ -2,4 +2,30 @@ pub mod pbb;
 pub mod utils;
 pub mod lighthouse;
 pub mod reth;
-pub mod reth_db;
\ No newline at end of file
+pub mod reth_db;
+pub mod storage;
+pub mod block;
+pub mod state_root;
+pub mod receipts;
+pub mod compare;
+pub mod chain;
+pub mod rpc;
+pub mod cli;
+pub mod builder;
+pub mod engine;
+pub mod relay;
+pub mod mock_relay;
+pub mod payment;
+pub mod selection;
+pub mod ordering;
+pub mod bundle;
+pub mod mev_share;
+pub mod order_pool;
+pub mod report;
+pub mod system_calls;
+pub mod blobs;
+pub mod error;
+pub mod recovery;
+pub mod tx_env;
+#[cfg(test)]
+mod test_utils;
\ No newline at end of file
@@ -1,12 +1,13 @@
 use futures_util::stream::StreamExt;
 use log::warn;
-use mev_share_sse::{
-    client::{EventStream, SseError},
-    EventClient,
-};
+use mev_share_sse::{client::EventStream, EventClient};
 use reth_rpc_types::beacon::events::PayloadAttributesEvent;
+use reth_rpc_types::beacon::BlsPublicKey;
+use serde::Deserialize;
 use std::net::{IpAddr, Ipv4Addr};
 
+use crate::error::PbbError;
+
 #[derive(Debug, Clone, clap::Parser)]
 pub struct BeaconEventsConfig {
     /// Beacon Node http server address
@@ -18,14 +19,6 @@ pub struct BeaconEventsConfig {
 }
 
 impl BeaconEventsConfig {
-    /// Creates a new instance of the beacon events service
-    pub fn new() -> Self {
-        Self {
-            cl_addr: Ipv4Addr::LOCALHOST.into(),
-            cl_port: 5052,
-        }
-    }
-
     /// Returns the http url of the beacon node
     fn http_base_url(&self) -> String {
         format!("http://{}:{}", self.cl_addr, self.cl_port)
@@ -37,10 +30,49 @@ impl BeaconEventsConfig {
     }
 
     /// Service that subscribes to beacon chain payload attributes events
-    pub async fn run(self) -> Result<PayloadAttributesEvent, SseError> {
+    pub async fn run(self) -> Result<PayloadAttributesEvent, PbbError> {
         let client = EventClient::default();
         let mut subscription = self.new_payload_attributes_subscription(&client).await;
-        subscription.next().await.unwrap()
+        match subscription.next().await {
+            Some(event) => Ok(event?),
+            None => Err(PbbError::BeaconStreamEnded),
+        }
+    }
+
+    /// Subscribes to the stream of all upcoming beacon chain payload attributes events
+    pub async fn subscribe(&self) -> EventStream<PayloadAttributesEvent> {
+        let client = EventClient::default();
+        self.new_payload_attributes_subscription(&client).await
+    }
+
+    /// Fetches the public key of the validator at `index` from the head state
+    pub async fn validator_pubkey(&self, index: u64) -> Result<BlsPublicKey, PbbError> {
+        #[derive(Deserialize)]
+        struct ValidatorResponse {
+            data: ValidatorData,
+        }
+        #[derive(Deserialize)]
+        struct ValidatorData {
+            validator: Validator,
+        }
+        #[derive(Deserialize)]
+        struct Validator {
+            pubkey: BlsPublicKey,
+        }
+
+        let url = format!(
+            "{}/eth/v1/beacon/states/head/validators/{}",
+            self.http_base_url(),
+            index
+        );
+        let response = reqwest::get(&url)
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(PbbError::BeaconApi)?
+            .json::<ValidatorResponse>()
+            .await
+            .map_err(PbbError::BeaconApi)?;
+        Ok(response.data.validator.pubkey)
     }
 
     // It can take a bit until the CL endpoint is live so we retry a few times
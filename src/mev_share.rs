@@ -0,0 +1,267 @@
+use std::collections::HashSet;
+use std::time::Duration;
+
+use futures_util::stream::StreamExt;
+use log::info;
+use log::warn;
+use mev_share_sse::EventClient;
+use reth_primitives::keccak256;
+use reth_primitives::Bytes;
+use reth_primitives::TransactionSignedEcRecovered;
+use reth_primitives::TxHash;
+use reth_primitives::B256;
+use reth_primitives::U64;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::bundle::decode_signed;
+use crate::error::PbbError;
+use crate::order_pool::SharedOrderPool;
+
+/// Blocks for which a bundle sent to `mev_sendBundle` can be included
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Inclusion {
+    pub block: U64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_block: Option<U64>,
+}
+
+/// Element of the body of a bundle sent to `mev_sendBundle`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged, rename_all = "camelCase")]
+pub enum BundleItem {
+    /// Transaction only known by the hash of its MEV-Share hint
+    Hash { hash: B256 },
+    /// EIP-2718 encoded signed transaction
+    #[serde(rename_all = "camelCase")]
+    Tx {
+        tx: Bytes,
+        #[serde(default)]
+        can_revert: bool,
+    },
+}
+
+/// Bundle as sent by searchers to `mev_sendBundle`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RawShareBundle {
+    #[serde(default)]
+    pub version: String,
+    pub inclusion: Inclusion,
+    pub body: Vec<BundleItem>,
+}
+
+/// Bundle backrunning the transaction of a MEV-Share hint
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackrunBundle {
+    /// Hash of the hint, which is the hash of the transaction being backrun
+    pub hint_hash: B256,
+    /// Transactions executed right after the hinted one
+    pub txs: Vec<TransactionSignedEcRecovered>,
+    pub reverting_tx_hashes: HashSet<TxHash>,
+    pub block: u64,
+    pub max_block: u64,
+}
+
+impl BackrunBundle {
+    /// Decodes a `mev_sendBundle` body made of a hint hash followed by signed transactions
+    pub fn decode(raw: RawShareBundle) -> Result<Self, PbbError> {
+        let mut body = raw.body.into_iter();
+        let Some(BundleItem::Hash { hash: hint_hash }) = body.next() else {
+            return Err(PbbError::MissingHint);
+        };
+
+        let mut txs = Vec::new();
+        let mut reverting_tx_hashes = HashSet::new();
+        for item in body {
+            let BundleItem::Tx { tx, can_revert } = item else {
+                return Err(PbbError::MultipleHints);
+            };
+            let tx = decode_signed(&tx)?;
+            if can_revert {
+                reverting_tx_hashes.insert(tx.hash());
+            }
+            txs.push(tx);
+        }
+        if txs.is_empty() {
+            return Err(PbbError::EmptyBundle);
+        }
+
+        let block = raw.inclusion.block.to::<u64>();
+        Ok(Self {
+            hint_hash,
+            txs,
+            reverting_tx_hashes,
+            block,
+            max_block: raw.inclusion.max_block.map_or(block, |max| max.to()),
+        })
+    }
+
+    /// Returns the bundle hash, the keccak of the hint hash followed by the backrun hashes
+    pub fn hash(&self) -> B256 {
+        let hashes: Vec<u8> = std::iter::once(self.hint_hash)
+            .chain(self.txs.iter().map(|tx| tx.hash()))
+            .flat_map(|hash| hash.0)
+            .collect();
+        keccak256(hashes)
+    }
+
+    /// Returns true if the backrun can go into the block `block_number`
+    pub fn targets(&self, block_number: u64) -> bool {
+        (self.block..=self.max_block).contains(&block_number)
+    }
+}
+
+/// Consumes the MEV-Share event stream at `endpoint`, storing every hint in `pool`.
+///
+/// The stream is reopened whenever it fails or ends, so the task runs forever.
+pub async fn run_hint_stream(endpoint: String, pool: SharedOrderPool) {
+    let client = EventClient::default();
+    loop {
+        let mut stream = match client.events(&endpoint).await {
+            Ok(stream) => stream,
+            Err(e) => {
+                warn!(
+                    "Failed to subscribe to MEV-Share events at {}: {:?}\nRetrying in 5 seconds...",
+                    endpoint, e
+                );
+                tokio::time::sleep(Duration::from_secs(5)).await;
+                continue;
+            }
+        };
+        info!("Subscribed to MEV-Share events at {}", endpoint);
+
+        while let Some(event) = stream.next().await {
+            match event {
+                Ok(event) => pool.write().unwrap().add_hint(event),
+                Err(e) => {
+                    warn!("MEV-Share event stream failed: {:?}, resubscribing", e);
+                    break;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::header::CONTENT_TYPE;
+    use axum::routing::get;
+    use axum::Router;
+    use reth_primitives::hex;
+    use reth_primitives::Signature;
+    use reth_primitives::TransactionSigned;
+
+    use super::*;
+    use crate::test_utils::signed_tx;
+
+    const HINT_HASH: B256 = B256::repeat_byte(0x42);
+    const SECRET_KEY: B256 = B256::repeat_byte(0x01);
+
+    fn share_bundle(body: Vec<BundleItem>) -> RawShareBundle {
+        RawShareBundle {
+            version: String::from("v0.1"),
+            inclusion: Inclusion {
+                block: U64::from(10),
+                max_block: None,
+            },
+            body,
+        }
+    }
+
+    fn tx_item(tx: &TransactionSigned, can_revert: bool) -> BundleItem {
+        BundleItem::Tx {
+            tx: tx.envelope_encoded(),
+            can_revert,
+        }
+    }
+
+    #[test]
+    fn decodes_a_backrun_of_a_hint() {
+        let first = signed_tx(SECRET_KEY, 0);
+        let second = signed_tx(SECRET_KEY, 1);
+        let backrun = BackrunBundle::decode(share_bundle(vec![
+            BundleItem::Hash { hash: HINT_HASH },
+            tx_item(&first, false),
+            tx_item(&second, true),
+        ]))
+        .unwrap();
+
+        assert_eq!(backrun.hint_hash, HINT_HASH);
+        let hashes: Vec<TxHash> = backrun.txs.iter().map(|tx| tx.hash()).collect();
+        assert_eq!(hashes, vec![first.hash(), second.hash()]);
+        assert_eq!(backrun.reverting_tx_hashes, HashSet::from([second.hash()]));
+        assert_eq!((backrun.block, backrun.max_block), (10, 10));
+        assert!(backrun.targets(10) && !backrun.targets(11));
+
+        let concatenated: Vec<u8> = [HINT_HASH, first.hash(), second.hash()]
+            .iter()
+            .flat_map(|hash| hash.0)
+            .collect();
+        assert_eq!(backrun.hash(), keccak256(concatenated));
+    }
+
+    #[test]
+    fn rejects_bundles_that_do_not_backrun_a_single_hint() {
+        let tx = signed_tx(SECRET_KEY, 0);
+        let invalid = [
+            // no hint
+            vec![tx_item(&tx, false)],
+            // two hints
+            vec![
+                BundleItem::Hash { hash: HINT_HASH },
+                BundleItem::Hash { hash: HINT_HASH },
+                tx_item(&tx, false),
+            ],
+            // nothing to backrun with
+            vec![BundleItem::Hash { hash: HINT_HASH }],
+        ];
+        for body in invalid {
+            assert!(BackrunBundle::decode(share_bundle(body)).is_err());
+        }
+    }
+
+    #[test]
+    fn rejects_transactions_without_a_signer() {
+        let unsigned = TransactionSigned::from_transaction_and_signature(
+            signed_tx(SECRET_KEY, 0).transaction,
+            Signature::default(),
+        );
+        let body = vec![
+            BundleItem::Hash { hash: HINT_HASH },
+            tx_item(&unsigned, false),
+        ];
+        assert!(BackrunBundle::decode(share_bundle(body)).is_err());
+    }
+
+    #[tokio::test]
+    async fn stores_the_hints_of_the_event_stream() {
+        let event = format!(
+            "data: {{\"hash\":\"0x{}\",\"logs\":null,\"txs\":null}}\n\n",
+            hex::encode(HINT_HASH)
+        );
+        let app = Router::new().route(
+            "/",
+            get(move || {
+                let event = event.clone();
+                async move { ([(CONTENT_TYPE, "text/event-stream")], event) }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let pool = SharedOrderPool::default();
+        pool.write().unwrap().set_block_number(1);
+        tokio::spawn(run_hint_stream(format!("http://{addr}"), pool.clone()));
+
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while pool.read().unwrap().hint(&HINT_HASH).is_none() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("the hint of the stream was never stored");
+    }
+}
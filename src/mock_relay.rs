@@ -0,0 +1,427 @@
+use std::fmt;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::RwLock;
+
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::header::CONTENT_TYPE;
+use axum::http::HeaderMap;
+use axum::http::StatusCode;
+use axum::routing::post;
+use axum::Router;
+use log::info;
+use log::warn;
+use reth_primitives::Address;
+use reth_primitives::TransactionSigned;
+use reth_primitives::B256;
+use reth_primitives::U256;
+use reth_rpc_types::beacon::relay::SignedBidSubmissionV3;
+use ssz::Decode;
+
+use crate::error::PbbError;
+use crate::relay::verify_bid_signature;
+use crate::relay::SUBMIT_BLOCK_PATH;
+
+/// Submissions accepted by the mock relay, in arrival order
+pub type AcceptedSubmissions = Arc<RwLock<Vec<SignedBidSubmissionV3>>>;
+
+/// In-process relay that validates block submissions the way a real relay
+/// checks them before simulation, so the submission flow runs offline
+#[derive(Debug, Clone)]
+pub struct MockRelay {
+    genesis_fork_version: [u8; 4],
+    accepted: AcceptedSubmissions,
+}
+
+impl MockRelay {
+    /// Creates a mock relay for the network with `genesis_fork_version`
+    pub fn new(genesis_fork_version: [u8; 4]) -> Self {
+        Self {
+            genesis_fork_version,
+            accepted: AcceptedSubmissions::default(),
+        }
+    }
+
+    /// Returns a handle to the submissions accepted so far
+    pub fn accepted(&self) -> AcceptedSubmissions {
+        self.accepted.clone()
+    }
+
+    /// Serves the submission endpoint on `addr` in the background, returning the bound address
+    pub async fn start(self, addr: SocketAddr) -> Result<SocketAddr, PbbError> {
+        let server_error = |source| PbbError::Server { addr, source };
+        let listener = tokio::net::TcpListener::bind(addr)
+            .await
+            .map_err(server_error)?;
+        let addr = listener.local_addr().map_err(server_error)?;
+        let app = Router::new()
+            .route(SUBMIT_BLOCK_PATH, post(submit_block))
+            .with_state(self);
+        tokio::spawn(async move {
+            if let Err(e) = axum::serve(listener, app).await {
+                warn!("Mock relay stopped: {:?}", e);
+            }
+        });
+        info!("Mock relay listening on {}", addr);
+        Ok(addr)
+    }
+
+    /// Checks a submission, returning why it is invalid
+    pub fn validate(&self, submission: &SignedBidSubmissionV3) -> Result<(), PbbError> {
+        let message = &submission.message;
+        let payload = &submission.execution_payload.payload_inner.payload_inner;
+
+        if message.block_hash != payload.block_hash {
+            return Err(SubmissionError::BlockHashMismatch {
+                bid: message.block_hash,
+                payload: payload.block_hash,
+            }
+            .into());
+        }
+        if message.parent_hash != payload.parent_hash {
+            return Err(SubmissionError::ParentHashMismatch {
+                bid: message.parent_hash,
+                payload: payload.parent_hash,
+            }
+            .into());
+        }
+        if message.gas_limit != payload.gas_limit || message.gas_used != payload.gas_used {
+            return Err(SubmissionError::GasMismatch {
+                bid_gas_used: message.gas_used,
+                bid_gas_limit: message.gas_limit,
+                payload_gas_used: payload.gas_used,
+                payload_gas_limit: payload.gas_limit,
+            }
+            .into());
+        }
+        if message.gas_used > message.gas_limit {
+            return Err(SubmissionError::GasAboveLimit.into());
+        }
+        if message.proposer_fee_recipient != payload.fee_recipient {
+            // the builder is the coinbase, the last transaction must pay the bid to the proposer
+            let payment = payload
+                .transactions
+                .last()
+                .ok_or(SubmissionError::MissingPayment)?;
+            let payment = TransactionSigned::decode_enveloped(&mut payment.as_ref())
+                .map_err(SubmissionError::InvalidPayment)?;
+            if payment.to() != Some(message.proposer_fee_recipient)
+                || payment.value() != message.value
+            {
+                return Err(SubmissionError::UnpaidProposer {
+                    value: message.value,
+                    fee_recipient: message.proposer_fee_recipient,
+                }
+                .into());
+            }
+        }
+        let blobs = submission.execution_payload.blob_gas_used as usize / BLOB_GAS_PER_BLOB;
+        if submission.blobs_bundle.commitments.len() != blobs {
+            return Err(SubmissionError::BlobCount {
+                commitments: submission.blobs_bundle.commitments.len(),
+                blobs,
+            }
+            .into());
+        }
+        verify_bid_signature(message, &submission.signature, self.genesis_fork_version)
+    }
+}
+
+/// Why the mock relay rejected a submission
+#[derive(Debug)]
+pub enum SubmissionError {
+    /// The bid and the payload name different blocks
+    BlockHashMismatch { bid: B256, payload: B256 },
+    /// The bid and the payload name different parents
+    ParentHashMismatch { bid: B256, payload: B256 },
+    /// The gas of the bid isn't the gas of the payload
+    GasMismatch {
+        bid_gas_used: u64,
+        bid_gas_limit: u64,
+        payload_gas_used: u64,
+        payload_gas_limit: u64,
+    },
+    /// The payload uses more gas than its limit
+    GasAboveLimit,
+    /// The builder is the coinbase but the payload has no transactions to pay the proposer
+    MissingPayment,
+    /// The last transaction of the payload can't be decoded
+    InvalidPayment(alloy_rlp::Error),
+    /// The last transaction of the payload does not pay the bid to the proposer
+    UnpaidProposer { value: U256, fee_recipient: Address },
+    /// The blobs bundle does not carry the blobs of the payload
+    BlobCount { commitments: usize, blobs: usize },
+}
+
+impl fmt::Display for SubmissionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BlockHashMismatch { bid, payload } => write!(
+                f,
+                "bid block hash {bid} does not match payload block hash {payload}"
+            ),
+            Self::ParentHashMismatch { bid, payload } => write!(
+                f,
+                "bid parent hash {bid} does not match payload parent hash {payload}"
+            ),
+            Self::GasMismatch {
+                bid_gas_used,
+                bid_gas_limit,
+                payload_gas_used,
+                payload_gas_limit,
+            } => write!(
+                f,
+                "bid gas {bid_gas_used}/{bid_gas_limit} does not match payload gas {payload_gas_used}/{payload_gas_limit}"
+            ),
+            Self::GasAboveLimit => write!(f, "payload uses more gas than its limit"),
+            Self::MissingPayment => write!(f, "payload has no proposer payment"),
+            Self::InvalidPayment(e) => write!(f, "invalid proposer payment: {e}"),
+            Self::UnpaidProposer {
+                value,
+                fee_recipient,
+            } => write!(
+                f,
+                "last transaction does not pay {value} to the proposer fee recipient {fee_recipient}"
+            ),
+            Self::BlobCount { commitments, blobs } => write!(
+                f,
+                "blobs bundle has {commitments} commitments, the payload blob gas covers {blobs} blobs"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SubmissionError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::InvalidPayment(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Blob gas used by a single blob
+const BLOB_GAS_PER_BLOB: usize = 1 << 17;
+
+async fn submit_block(
+    State(relay): State<MockRelay>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> (StatusCode, String) {
+    let is_ssz = headers
+        .get(CONTENT_TYPE)
+        .is_some_and(|content_type| content_type == "application/octet-stream");
+    let submission = if is_ssz {
+        SignedBidSubmissionV3::from_ssz_bytes(&body)
+            .map_err(|e| format!("invalid SSZ submission: {:?}", e))
+    } else {
+        serde_json::from_slice::<SignedBidSubmissionV3>(&body)
+            .map_err(|e| format!("invalid JSON submission: {}", e))
+    };
+    let submission = match submission {
+        Ok(submission) => submission,
+        Err(e) => return (StatusCode::BAD_REQUEST, e),
+    };
+
+    if let Err(e) = relay.validate(&submission) {
+        warn!(
+            "Mock relay rejected block {}: {}",
+            submission.message.block_hash, e
+        );
+        return (StatusCode::BAD_REQUEST, e.to_string());
+    }
+    info!(
+        "Mock relay accepted block {} for slot {} with value {}",
+        submission.message.block_hash, submission.message.slot, submission.message.value
+    );
+    relay.accepted.write().unwrap().push(submission);
+    (StatusCode::OK, String::new())
+}
+
+#[cfg(test)]
+mod tests {
+    use reth_primitives::Block;
+    use reth_primitives::Header;
+    use reth_primitives::Signature;
+    use reth_primitives::Transaction;
+    use reth_primitives::TxEip1559;
+    use reth_primitives::TxKind;
+    use reth_rpc_types::beacon::BlsPublicKey;
+    use reth_rpc_types::engine::BlobsBundleV1;
+
+    use super::*;
+    use crate::block::BuiltBlock;
+    use crate::builder::SlotPayload;
+    use crate::relay::BuilderSigner;
+    use crate::relay::Proposer;
+    use crate::relay::RelayClient;
+    use crate::relay::SubmissionEncoding;
+    use crate::report::BlockValue;
+    use crate::report::BuildReport;
+
+    const GENESIS_FORK_VERSION: [u8; 4] = [0, 0, 0, 0];
+    const BUILDER_SECRET_KEY: &str =
+        "3d8c3ff28ebc84e1fa1a1e6a5a8d0c4b0d6cbd2a5c5d0b6d8f1c4e7b3a2b1c0d";
+
+    /// Payload for the slot with `transactions`, paying its fees to `fee_recipient`
+    fn slot_payload(fee_recipient: Address, transactions: Vec<TransactionSigned>) -> SlotPayload {
+        let block = Block {
+            header: Header {
+                number: 1,
+                beneficiary: fee_recipient,
+                gas_limit: 30_000_000,
+                base_fee_per_gas: Some(7),
+                ..Default::default()
+            },
+            body: transactions,
+            ..Default::default()
+        }
+        .seal_slow();
+        SlotPayload {
+            slot: 32,
+            value: U256::from(1),
+            blobs_bundle: BlobsBundleV1 {
+                commitments: Vec::new(),
+                proofs: Vec::new(),
+                blobs: Vec::new(),
+            },
+            built: BuiltBlock {
+                report: BuildReport {
+                    block_number: block.number,
+                    coinbase: fee_recipient,
+                    proposer_payment: None,
+                    total: BlockValue::default(),
+                    txs: Vec::new(),
+                    bundles: Vec::new(),
+                },
+                block,
+                senders: Vec::new(),
+                receipts: Vec::new(),
+                bundles: Vec::new(),
+                excluded: Vec::new(),
+                rejected: Vec::new(),
+                excluded_bundles: Vec::new(),
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn accepts_json_and_ssz_submissions() {
+        let relay = MockRelay::new(GENESIS_FORK_VERSION);
+        let accepted = relay.accepted();
+        let addr = relay.start("127.0.0.1:0".parse().unwrap()).await.unwrap();
+
+        let proposer = Proposer {
+            pubkey: BlsPublicKey::repeat_byte(0x44),
+            fee_recipient: Address::repeat_byte(0x55),
+        };
+        let payload = slot_payload(proposer.fee_recipient, Vec::new());
+        for encoding in [SubmissionEncoding::Json, SubmissionEncoding::Ssz] {
+            let signer = BuilderSigner::from_hex(BUILDER_SECRET_KEY, GENESIS_FORK_VERSION).unwrap();
+            let client = RelayClient::new(vec![format!("http://{addr}")], signer, encoding);
+            client.submit(&payload, &proposer).await.unwrap();
+        }
+
+        let accepted = accepted.read().unwrap();
+        assert_eq!(accepted.len(), 2);
+        for submission in accepted.iter() {
+            assert_eq!(submission.message.block_hash, payload.built.block.hash());
+            assert_eq!(submission.message.proposer_pubkey, proposer.pubkey);
+            assert_eq!(submission.message.value, payload.value);
+        }
+    }
+
+    fn proposer() -> Proposer {
+        Proposer {
+            pubkey: BlsPublicKey::repeat_byte(0x44),
+            fee_recipient: Address::repeat_byte(0x55),
+        }
+    }
+
+    /// Submission of `payload` signed for `genesis_fork_version`
+    fn submission(payload: &SlotPayload, genesis_fork_version: [u8; 4]) -> SignedBidSubmissionV3 {
+        let signer = BuilderSigner::from_hex(BUILDER_SECRET_KEY, genesis_fork_version).unwrap();
+        RelayClient::new(Vec::new(), signer, SubmissionEncoding::Json)
+            .signed_submission(payload, &proposer())
+    }
+
+    /// Transfer of `value` from the builder to `to`, closing a builder-key block
+    fn payment(to: Address, value: U256) -> TransactionSigned {
+        TransactionSigned::from_transaction_and_signature(
+            Transaction::Eip1559(TxEip1559 {
+                chain_id: 1,
+                gas_limit: 21_000,
+                max_fee_per_gas: 7,
+                to: TxKind::Call(to),
+                value,
+                ..Default::default()
+            }),
+            Signature::default(),
+        )
+    }
+
+    fn rejection(submission: &SignedBidSubmissionV3) -> String {
+        MockRelay::new(GENESIS_FORK_VERSION)
+            .validate(submission)
+            .unwrap_err()
+            .to_string()
+    }
+
+    #[test]
+    fn rejects_a_signature_for_another_network() {
+        let payload = slot_payload(proposer().fee_recipient, Vec::new());
+        let valid = submission(&payload, GENESIS_FORK_VERSION);
+        assert!(MockRelay::new(GENESIS_FORK_VERSION)
+            .validate(&valid)
+            .is_ok());
+
+        let holesky = submission(&payload, [0x01, 0x01, 0x70, 0x00]);
+        assert!(rejection(&holesky).contains("signature"));
+
+        let mut tampered = valid;
+        tampered.message.value += U256::from(1);
+        assert!(rejection(&tampered).contains("signature"));
+    }
+
+    #[test]
+    fn rejects_a_block_hash_mismatch() {
+        let payload = slot_payload(proposer().fee_recipient, Vec::new());
+        let mut submission = submission(&payload, GENESIS_FORK_VERSION);
+        submission.message.block_hash = B256::repeat_byte(0x66);
+        assert!(rejection(&submission).contains("block hash"));
+    }
+
+    #[test]
+    fn rejects_a_missing_or_underpaying_proposer_payment() {
+        let builder = Address::repeat_byte(0x77);
+        let unpaid = slot_payload(builder, Vec::new());
+        assert!(
+            rejection(&submission(&unpaid, GENESIS_FORK_VERSION)).contains("no proposer payment")
+        );
+
+        let mut underpaid = slot_payload(
+            builder,
+            vec![payment(proposer().fee_recipient, U256::from(1))],
+        );
+        underpaid.value = U256::from(2);
+        assert!(rejection(&submission(&underpaid, GENESIS_FORK_VERSION)).contains("does not pay"));
+
+        let mut paid = slot_payload(
+            builder,
+            vec![payment(proposer().fee_recipient, U256::from(2))],
+        );
+        paid.value = U256::from(2);
+        assert!(MockRelay::new(GENESIS_FORK_VERSION)
+            .validate(&submission(&paid, GENESIS_FORK_VERSION))
+            .is_ok());
+    }
+
+    #[test]
+    fn rejects_blobs_the_payload_does_not_carry() {
+        let mut payload = slot_payload(proposer().fee_recipient, Vec::new());
+        payload.blobs_bundle.commitments.push(Default::default());
+        let submission = submission(&payload, GENESIS_FORK_VERSION);
+        assert!(rejection(&submission).contains("blob"));
+    }
+}
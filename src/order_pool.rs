@@ -0,0 +1,346 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::RwLock;
+
+use jsonrpsee::core::async_trait;
+use jsonrpsee::core::RpcResult;
+use jsonrpsee::proc_macros::rpc;
+use jsonrpsee::server::Server;
+use jsonrpsee::server::ServerHandle;
+use jsonrpsee::types::error::INVALID_PARAMS_CODE;
+use jsonrpsee::types::ErrorObjectOwned;
+use log::debug;
+use log::info;
+use mev_share_sse::Event;
+use reth_primitives::TransactionSignedEcRecovered;
+use reth_primitives::B256;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::bundle::Bundle;
+use crate::bundle::RawBundle;
+use crate::error::PbbError;
+use crate::mev_share::BackrunBundle;
+use crate::mev_share::RawShareBundle;
+
+/// Number of blocks a MEV-Share hint can be backrun for after it is received
+const HINT_TTL_BLOCKS: u64 = 25;
+
+/// MEV-Share hint received from the event stream
+#[derive(Debug, Clone)]
+pub struct ReceivedHint {
+    pub event: Event,
+    /// Block that was being built when the hint arrived
+    pub block_number: u64,
+    /// Hinted transaction, once it shows up in the public mempool. Hints of private
+    /// transactions never get it, the matchmaker doesn't share its order flow with the
+    /// builder
+    pub tx: Option<TransactionSignedEcRecovered>,
+}
+
+impl ReceivedHint {
+    /// Returns true if the hint can still be backrun in the block `block_number`
+    pub fn is_live(&self, block_number: u64) -> bool {
+        block_number <= self.block_number + HINT_TTL_BLOCKS
+    }
+}
+
+/// Backrun accepted into the pool with the transaction it backruns
+#[derive(Debug, Clone)]
+struct PendingBackrun {
+    hinted: TransactionSignedEcRecovered,
+    backrun: BackrunBundle,
+}
+
+/// Orders received besides the mempool: bundles, MEV-Share hints and the bundles
+/// backrunning them
+#[derive(Debug, Default)]
+pub struct OrderPool {
+    /// Block the builder currently builds, unknown until the first payload attributes
+    block_number: Option<u64>,
+    hints: HashMap<B256, ReceivedHint>,
+    bundles: Vec<Bundle>,
+    backruns: Vec<PendingBackrun>,
+}
+
+/// Order pool shared by the builder, the hint stream and the order RPC
+pub type SharedOrderPool = Arc<RwLock<OrderPool>>;
+
+impl OrderPool {
+    /// Stores a MEV-Share hint so that it can be backrun, dropping it while the block
+    /// being built is unknown since its TTL can't be told
+    pub fn add_hint(&mut self, event: Event) {
+        let hash = B256::from(event.hash.0);
+        let Some(block_number) = self.block_number else {
+            debug!("Dropping MEV-Share hint {} received before any block", hash);
+            return;
+        };
+        debug!(
+            "MEV-Share hint {} with {} txs and {} logs",
+            hash,
+            event.transactions.len(),
+            event.logs.len()
+        );
+        self.hints.insert(
+            hash,
+            ReceivedHint {
+                event,
+                block_number,
+                tx: None,
+            },
+        );
+    }
+
+    /// Returns the hint with `hash` if it is still backrunnable
+    pub fn hint(&self, hash: &B256) -> Option<&ReceivedHint> {
+        let block_number = self.block_number?;
+        self.hints
+            .get(hash)
+            .filter(|hint| hint.is_live(block_number))
+    }
+
+    /// Adds a bundle, bundles are placed in a block in the order they are added
+    pub fn add_bundle(&mut self, bundle: Bundle) {
+        self.bundles.push(bundle);
+    }
+
+    /// Attaches the transactions of `mempool` to the hints they were hinted by
+    pub fn observe_mempool(&mut self, mempool: &[TransactionSignedEcRecovered]) {
+        for tx in mempool {
+            if let Some(hint) = self.hints.get_mut(&tx.hash()) {
+                hint.tx.get_or_insert_with(|| tx.clone());
+            }
+        }
+    }
+
+    /// Adds a bundle backrunning a hint, which must have been received already and
+    /// not be expired.
+    ///
+    /// Only hints whose transaction was seen in the public mempool can be backrun, the
+    /// builder doesn't receive the private transactions MEV-Share hints are usually
+    /// about, so backruns of those are rejected.
+    pub fn add_backrun(&mut self, backrun: BackrunBundle) -> Result<(), PbbError> {
+        let Some(hint) = self.hint(&backrun.hint_hash) else {
+            return Err(PbbError::UnknownHint {
+                hash: backrun.hint_hash,
+            });
+        };
+        let Some(hinted) = hint.tx.clone() else {
+            return Err(PbbError::PrivateHint {
+                hash: backrun.hint_hash,
+            });
+        };
+        if let Some(block_number) = self
+            .block_number
+            .filter(|block_number| backrun.max_block < *block_number)
+        {
+            return Err(PbbError::PastBackrun {
+                max_block: backrun.max_block,
+                block_number,
+            });
+        }
+        self.backruns.push(PendingBackrun { hinted, backrun });
+        Ok(())
+    }
+
+    /// Moves the pool to the block `block_number`, dropping the orders that can't be
+    /// included anymore
+    pub fn set_block_number(&mut self, block_number: u64) {
+        self.block_number = Some(block_number);
+        self.bundles
+            .retain(|bundle| bundle.block_number >= block_number);
+        self.backruns
+            .retain(|pending| pending.backrun.max_block >= block_number);
+        self.hints.retain(|_, hint| hint.is_live(block_number));
+    }
+
+    /// Returns the bundles to place in the block `block_number`, in order.
+    ///
+    /// Backruns follow the plain bundles, preceded by the hinted transaction, and keep
+    /// the hash `mev_sendBundle` returned for them.
+    pub fn bundles_for(&self, block_number: u64) -> Vec<Bundle> {
+        let bundles = self
+            .bundles
+            .iter()
+            .filter(|bundle| bundle.block_number == block_number)
+            .cloned();
+        let backruns = self
+            .backruns
+            .iter()
+            .filter(|pending| pending.backrun.targets(block_number))
+            .map(|PendingBackrun { hinted, backrun }| {
+                let txs = std::iter::once(hinted.clone())
+                    .chain(backrun.txs.iter().cloned())
+                    .collect();
+                Bundle::new(txs, block_number, backrun.reverting_tx_hashes.clone())
+                    .with_hash(backrun.hash())
+            });
+        bundles.chain(backruns).collect()
+    }
+}
+
+/// Hash under which a bundle was accepted
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleHash {
+    pub bundle_hash: B256,
+}
+
+/// Bundle submission endpoints of searchers
+#[rpc(server)]
+pub trait OrderApi {
+    #[method(name = "eth_sendBundle")]
+    async fn send_bundle(&self, bundle: RawBundle) -> RpcResult<BundleHash>;
+
+    /// Backruns a MEV-Share hint. Only hints of transactions the builder saw in the
+    /// public mempool are accepted, see [`OrderPool::add_backrun`]
+    #[method(name = "mev_sendBundle")]
+    async fn send_share_bundle(&self, bundle: RawShareBundle) -> RpcResult<BundleHash>;
+}
+
+/// Accepts bundles into the order pool
+pub struct OrderRpc {
+    pool: SharedOrderPool,
+}
+
+impl OrderRpc {
+    /// Creates a new handler adding the bundles to `pool`
+    pub fn new(pool: SharedOrderPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl OrderApiServer for OrderRpc {
+    async fn send_bundle(&self, bundle: RawBundle) -> RpcResult<BundleHash> {
+        let bundle = Bundle::decode(bundle).map_err(invalid_params)?;
+        let bundle_hash = bundle.hash();
+        self.pool.write().unwrap().add_bundle(bundle);
+        Ok(BundleHash { bundle_hash })
+    }
+
+    async fn send_share_bundle(&self, bundle: RawShareBundle) -> RpcResult<BundleHash> {
+        let backrun = BackrunBundle::decode(bundle).map_err(invalid_params)?;
+        let bundle_hash = backrun.hash();
+        self.pool
+            .write()
+            .unwrap()
+            .add_backrun(backrun)
+            .map_err(invalid_params)?;
+        Ok(BundleHash { bundle_hash })
+    }
+}
+
+fn invalid_params(e: PbbError) -> ErrorObjectOwned {
+    ErrorObjectOwned::owned(INVALID_PARAMS_CODE, e.to_string(), None::<()>)
+}
+
+/// Starts the bundle submission server on `addr`
+pub async fn start_order_server(
+    addr: SocketAddr,
+    pool: SharedOrderPool,
+) -> Result<ServerHandle, PbbError> {
+    let server_error = |source| PbbError::Server { addr, source };
+    let server = Server::builder().build(addr).await.map_err(server_error)?;
+    let addr = server.local_addr().map_err(server_error)?;
+    let handle = server.start(OrderRpc::new(pool).into_rpc());
+    info!("Bundle server listening on {}", addr);
+    Ok(handle)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use reth_primitives::hex;
+
+    use super::*;
+    use crate::test_utils::signed_tx;
+
+    const SECRET_KEY: B256 = B256::repeat_byte(0x01);
+
+    fn hint_event(hash: B256) -> Event {
+        serde_json::from_value(serde_json::json!({
+            "hash": format!("0x{}", hex::encode(hash)),
+            "logs": null,
+            "txs": null,
+        }))
+        .unwrap()
+    }
+
+    fn backrun(hint_hash: B256, block: u64, max_block: u64) -> BackrunBundle {
+        BackrunBundle {
+            hint_hash,
+            txs: vec![signed_tx(SECRET_KEY, 1).into_ecrecovered().unwrap()],
+            reverting_tx_hashes: HashSet::new(),
+            block,
+            max_block,
+        }
+    }
+
+    #[test]
+    fn drops_hints_received_before_any_block() {
+        let hash = B256::repeat_byte(0x42);
+        let mut pool = OrderPool::default();
+        pool.add_hint(hint_event(hash));
+        pool.set_block_number(10);
+        assert!(pool.hint(&hash).is_none());
+
+        pool.add_hint(hint_event(hash));
+        assert_eq!(pool.hint(&hash).unwrap().block_number, 10);
+    }
+
+    #[test]
+    fn expires_hints_past_their_ttl() {
+        let hinted = signed_tx(SECRET_KEY, 0).into_ecrecovered().unwrap();
+        let hash = hinted.hash();
+        let mut pool = OrderPool::default();
+        pool.set_block_number(10);
+        pool.add_hint(hint_event(hash));
+        pool.observe_mempool(&[hinted]);
+
+        pool.set_block_number(10 + HINT_TTL_BLOCKS);
+        assert!(pool.hint(&hash).is_some());
+        pool.add_backrun(backrun(hash, 10, 40)).unwrap();
+
+        pool.set_block_number(11 + HINT_TTL_BLOCKS);
+        assert!(pool.hint(&hash).is_none());
+        assert!(pool.add_backrun(backrun(hash, 10, 40)).is_err());
+    }
+
+    #[test]
+    fn rejects_backruns_of_transactions_missing_from_the_mempool() {
+        let hinted = signed_tx(SECRET_KEY, 0).into_ecrecovered().unwrap();
+        let mut pool = OrderPool::default();
+        pool.set_block_number(10);
+        pool.add_hint(hint_event(hinted.hash()));
+
+        // a private transaction never shows up in the mempool
+        pool.observe_mempool(&[signed_tx(SECRET_KEY, 5).into_ecrecovered().unwrap()]);
+        assert!(matches!(
+            pool.add_backrun(backrun(hinted.hash(), 10, 11)),
+            Err(PbbError::PrivateHint { hash }) if hash == hinted.hash()
+        ));
+        assert!(pool.bundles_for(10).is_empty());
+    }
+
+    #[test]
+    fn backruns_keep_the_hash_returned_to_the_searcher() {
+        let hinted = signed_tx(SECRET_KEY, 0).into_ecrecovered().unwrap();
+        let mut pool = OrderPool::default();
+        pool.set_block_number(10);
+        pool.add_hint(hint_event(hinted.hash()));
+        pool.observe_mempool(&[hinted.clone()]);
+        let backrun = backrun(hinted.hash(), 10, 11);
+        pool.add_backrun(backrun.clone()).unwrap();
+
+        // the hinted transaction leaving the mempool doesn't drop the backrun
+        pool.observe_mempool(&[]);
+        let bundles = pool.bundles_for(10);
+        assert_eq!(bundles.len(), 1);
+        assert_eq!(bundles[0].hash(), backrun.hash());
+        assert_eq!(bundles[0].txs[0].hash(), hinted.hash());
+        assert_eq!(bundles[0].txs[1..], backrun.txs[..]);
+    }
+}
@@ -0,0 +1,276 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::collections::HashMap;
+use std::collections::VecDeque;
+
+use reth_primitives::Address;
+use reth_primitives::TransactionSignedEcRecovered;
+use reth_provider::AccountReader;
+use reth_provider::ProviderResult;
+
+use crate::selection::ExcludedTx;
+use crate::selection::ExclusionReason;
+
+/// Transactions in an order that is valid for the nonces of their senders, and the
+/// ones that can't be included at all
+#[derive(Debug, Clone, Default)]
+pub struct Ordering {
+    pub ordered: Vec<TransactionSignedEcRecovered>,
+    pub excluded: Vec<ExcludedTx>,
+}
+
+/// Orders `txs` so that every sender's transactions come in ascending nonce order
+/// starting at its nonce in `state`, interleaving senders by effective tip.
+///
+/// `next_nonces` holds the next nonce of the senders of transactions placed ahead of
+/// `txs` in the block, like the ones of the bundles, and takes over their nonce in `state`.
+///
+/// Transactions with a nonce below the account nonce, a nonce already taken by a
+/// bundle or by a better paying transaction, or a nonce past a gap are excluded.
+/// Senders paying the same tip keep the order in which their next transactions appear
+/// in `txs`.
+pub fn order_by_sender(
+    txs: Vec<TransactionSignedEcRecovered>,
+    state: &impl AccountReader,
+    next_nonces: &HashMap<Address, u64>,
+    base_fee: u64,
+) -> ProviderResult<Ordering> {
+    let mut ordering = Ordering::default();
+    let tip =
+        |tx: &TransactionSignedEcRecovered| tx.effective_tip_per_gas(Some(base_fee)).unwrap_or(0);
+
+    let mut by_sender: HashMap<Address, Vec<(usize, TransactionSignedEcRecovered)>> =
+        HashMap::new();
+    for (index, tx) in txs.into_iter().enumerate() {
+        by_sender.entry(tx.signer()).or_default().push((index, tx));
+    }
+
+    let mut queues: Vec<VecDeque<(usize, TransactionSignedEcRecovered)>> = Vec::new();
+    for (sender, mut txs) in by_sender {
+        let state_nonce = state
+            .basic_account(sender)?
+            .map(|account| account.nonce)
+            .unwrap_or_default();
+        let account_nonce = state_nonce.max(next_nonces.get(&sender).copied().unwrap_or_default());
+        // best paying first among the transactions with the same nonce
+        txs.sort_by_key(|(index, tx)| (tx.nonce(), Reverse(tip(tx)), *index));
+
+        let mut queue = VecDeque::new();
+        let mut expected = account_nonce;
+        for (index, tx) in txs {
+            let nonce = tx.nonce();
+            let reason = if nonce < state_nonce {
+                Some(ExclusionReason::StaleNonce {
+                    nonce,
+                    account_nonce: state_nonce,
+                })
+            } else if nonce < account_nonce {
+                Some(ExclusionReason::NonceTakenByBundle {
+                    nonce,
+                    next_nonce: account_nonce,
+                })
+            } else if nonce < expected {
+                Some(ExclusionReason::DuplicateNonce { nonce })
+            } else if nonce > expected {
+                Some(ExclusionReason::NonceGap { nonce, expected })
+            } else {
+                None
+            };
+            match reason {
+                Some(reason) => ordering.excluded.push(ExcludedTx {
+                    hash: tx.hash(),
+                    reason,
+                }),
+                None => {
+                    expected += 1;
+                    queue.push_back((index, tx));
+                }
+            }
+        }
+        if !queue.is_empty() {
+            queues.push(queue);
+        }
+    }
+
+    // the head of every sender's queue competes on tip, ties going to the earliest in `txs`
+    let mut heads: BinaryHeap<(u128, Reverse<usize>, usize)> = queues
+        .iter()
+        .enumerate()
+        .filter_map(|(sender, queue)| {
+            queue
+                .front()
+                .map(|(index, tx)| (tip(tx), Reverse(*index), sender))
+        })
+        .collect();
+    while let Some((_, _, sender)) = heads.pop() {
+        let (_, tx) = queues[sender]
+            .pop_front()
+            .expect("queued senders have a head");
+        ordering.ordered.push(tx);
+        if let Some((index, next)) = queues[sender].front() {
+            heads.push((tip(next), Reverse(*index), sender));
+        }
+    }
+
+    Ok(ordering)
+}
+
+#[cfg(test)]
+mod tests {
+    use reth_primitives::TxHash;
+
+    use super::*;
+    use crate::test_utils::tx;
+    use crate::test_utils::MockAccounts;
+    use crate::test_utils::TRANSFER_GAS;
+
+    const ALICE: Address = Address::repeat_byte(0xa1);
+    const BOB: Address = Address::repeat_byte(0xb0);
+    const BASE_FEE: u64 = 10;
+
+    fn tx_with_tip(sender: Address, nonce: u64, tip: u128) -> TransactionSignedEcRecovered {
+        tx(sender, nonce, TRANSFER_GAS, 100, tip)
+    }
+
+    fn hashes(txs: &[TransactionSignedEcRecovered]) -> Vec<TxHash> {
+        txs.iter().map(|tx| tx.hash()).collect()
+    }
+
+    #[test]
+    fn interleaves_senders_by_tip_in_nonce_order() {
+        let txs = vec![
+            tx_with_tip(ALICE, 1, 5),
+            tx_with_tip(ALICE, 0, 1),
+            tx_with_tip(BOB, 0, 3),
+        ];
+        let ordering = order_by_sender(
+            txs.clone(),
+            &MockAccounts::default(),
+            &HashMap::new(),
+            BASE_FEE,
+        )
+        .unwrap();
+        assert_eq!(
+            hashes(&ordering.ordered),
+            vec![txs[2].hash(), txs[1].hash(), txs[0].hash()]
+        );
+        assert!(ordering.excluded.is_empty());
+    }
+
+    #[test]
+    fn breaks_tip_ties_by_position() {
+        let txs = vec![tx_with_tip(BOB, 0, 2), tx_with_tip(ALICE, 0, 2)];
+        let ordering = order_by_sender(
+            txs.clone(),
+            &MockAccounts::default(),
+            &HashMap::new(),
+            BASE_FEE,
+        )
+        .unwrap();
+        assert_eq!(hashes(&ordering.ordered), hashes(&txs));
+    }
+
+    #[test]
+    fn keeps_the_best_paying_duplicate_nonce() {
+        let txs = vec![tx_with_tip(ALICE, 0, 1), tx_with_tip(ALICE, 0, 2)];
+        let ordering = order_by_sender(
+            txs.clone(),
+            &MockAccounts::default(),
+            &HashMap::new(),
+            BASE_FEE,
+        )
+        .unwrap();
+        assert_eq!(hashes(&ordering.ordered), vec![txs[1].hash()]);
+        assert_eq!(
+            ordering.excluded,
+            vec![ExcludedTx {
+                hash: txs[0].hash(),
+                reason: ExclusionReason::DuplicateNonce { nonce: 0 },
+            }]
+        );
+    }
+
+    #[test]
+    fn excludes_stale_nonces_and_gaps() {
+        let state = MockAccounts::default().with_nonce(ALICE, 3);
+        let txs = vec![
+            tx_with_tip(ALICE, 2, 1),
+            tx_with_tip(ALICE, 3, 1),
+            tx_with_tip(ALICE, 5, 1),
+        ];
+        let ordering = order_by_sender(txs.clone(), &state, &HashMap::new(), BASE_FEE).unwrap();
+        assert_eq!(hashes(&ordering.ordered), vec![txs[1].hash()]);
+        assert_eq!(
+            ordering.excluded,
+            vec![
+                ExcludedTx {
+                    hash: txs[0].hash(),
+                    reason: ExclusionReason::StaleNonce {
+                        nonce: 2,
+                        account_nonce: 3,
+                    },
+                },
+                ExcludedTx {
+                    hash: txs[2].hash(),
+                    reason: ExclusionReason::NonceGap {
+                        nonce: 5,
+                        expected: 4,
+                    },
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn starts_after_the_nonces_taken_ahead() {
+        let state = MockAccounts::default().with_nonce(ALICE, 1);
+        // a bundle ahead of the transactions already uses nonces 1 and 2
+        let next_nonces = HashMap::from([(ALICE, 3)]);
+        let txs = vec![
+            tx_with_tip(ALICE, 0, 1),
+            tx_with_tip(ALICE, 2, 1),
+            tx_with_tip(ALICE, 3, 1),
+        ];
+        let ordering = order_by_sender(txs.clone(), &state, &next_nonces, BASE_FEE).unwrap();
+        assert_eq!(hashes(&ordering.ordered), vec![txs[2].hash()]);
+        assert_eq!(
+            ordering.excluded,
+            vec![
+                ExcludedTx {
+                    hash: txs[0].hash(),
+                    reason: ExclusionReason::StaleNonce {
+                        nonce: 0,
+                        account_nonce: 1,
+                    },
+                },
+                ExcludedTx {
+                    hash: txs[1].hash(),
+                    reason: ExclusionReason::NonceTakenByBundle {
+                        nonce: 2,
+                        next_nonce: 3,
+                    },
+                },
+            ]
+        );
+        // the bundle may be pruned or left out of a later build
+        assert!(ordering.excluded[1].reason.is_deferred());
+    }
+
+    #[test]
+    fn caps_the_tip_at_the_fee_left_over_the_base_fee() {
+        // pays a tip of 50 but only 15 over the base fee
+        let capped = tx(ALICE, 0, TRANSFER_GAS, 25, 50);
+        let txs = vec![capped, tx_with_tip(BOB, 0, 20)];
+        let ordering = order_by_sender(
+            txs.clone(),
+            &MockAccounts::default(),
+            &HashMap::new(),
+            BASE_FEE,
+        )
+        .unwrap();
+        assert_eq!(
+            hashes(&ordering.ordered),
+            vec![txs[1].hash(), txs[0].hash()]
+        );
+    }
+}
@@ -0,0 +1,123 @@
+use pevm::PevmTxExecutionResult;
+use reth_primitives::constants::MIN_TRANSACTION_GAS;
+use reth_primitives::hex;
+use reth_primitives::public_key_to_address;
+use reth_primitives::sign_message;
+use reth_primitives::Account;
+use reth_primitives::Address;
+use reth_primitives::Bytes;
+use reth_primitives::Transaction;
+use reth_primitives::TransactionSigned;
+use reth_primitives::TxEip1559;
+use reth_primitives::TxKind;
+use reth_primitives::B256;
+use reth_primitives::U256;
+use secp256k1::PublicKey;
+use secp256k1::SecretKey;
+use secp256k1::SECP256K1;
+
+use crate::error::PbbError;
+
+/// Builder-key mode: the block coinbase is the builder, which pays the proposer
+/// out of its profit with a transfer at the end of the block
+#[derive(Clone)]
+pub struct BuilderPayment {
+    secret_key: B256,
+    address: Address,
+    /// Part of the coinbase profit the builder keeps
+    pub margin: U256,
+}
+
+impl BuilderPayment {
+    /// Creates the payment settings from a hex encoded secp256k1 secret key
+    pub fn from_hex(secret_key: &str, margin: U256) -> Result<Self, PbbError> {
+        let key =
+            SecretKey::from_slice(&hex::decode(secret_key)?).map_err(PbbError::InvalidSecretKey)?;
+        let secret_key = B256::from(key.secret_bytes());
+        let address = public_key_to_address(PublicKey::from_secret_key(SECP256K1, &key));
+        Ok(Self {
+            secret_key,
+            address,
+            margin,
+        })
+    }
+
+    /// Returns the builder address, used as the block coinbase
+    pub fn address(&self) -> Address {
+        self.address
+    }
+
+    /// Signs the transfer of `value` to `fee_recipient`.
+    ///
+    /// The transfer pays no priority fee, the builder being the coinbase.
+    pub fn payment_transaction(
+        &self,
+        chain_id: u64,
+        nonce: u64,
+        base_fee: u64,
+        fee_recipient: Address,
+        value: U256,
+    ) -> Result<TransactionSigned, PbbError> {
+        let transaction = Transaction::Eip1559(TxEip1559 {
+            chain_id,
+            nonce,
+            gas_limit: MIN_TRANSACTION_GAS,
+            max_fee_per_gas: base_fee as u128,
+            max_priority_fee_per_gas: 0,
+            to: TxKind::Call(fee_recipient),
+            value,
+            access_list: Default::default(),
+            input: Bytes::new(),
+        });
+        let signature = sign_message(self.secret_key, transaction.signature_hash())
+            .map_err(PbbError::PaymentSigning)?;
+        Ok(TransactionSigned::from_transaction_and_signature(
+            transaction,
+            signature,
+        ))
+    }
+
+    /// Returns the value paid to the proposer out of `coinbase_delta`, once the
+    /// margin and the gas of the payment itself are taken out
+    pub fn payment_value(&self, coinbase_delta: U256, base_fee: u64) -> Result<U256, PbbError> {
+        let payment_gas_cost = U256::from(MIN_TRANSACTION_GAS) * U256::from(base_fee);
+        coinbase_delta
+            .checked_sub(self.margin)
+            .and_then(|value| value.checked_sub(payment_gas_cost))
+            .ok_or(PbbError::InsufficientProfit {
+                profit: coinbase_delta,
+                margin: self.margin,
+                payment_gas_cost,
+            })
+    }
+}
+
+impl std::fmt::Debug for BuilderPayment {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BuilderPayment")
+            .field("address", &self.address)
+            .field("margin", &self.margin)
+            .finish()
+    }
+}
+
+/// Returns the account of `address` after the last transaction that wrote it,
+/// or `before` if no transaction did
+pub fn account_after(
+    address: Address,
+    before: Option<Account>,
+    results: &[PevmTxExecutionResult],
+) -> Option<Account> {
+    results
+        .iter()
+        .rev()
+        .find_map(|result| result.state.get(&address))
+        .map(|account| {
+            account.as_ref().map(|account| Account {
+                nonce: account.basic.nonce,
+                balance: account.basic.balance,
+                bytecode_hash: account.basic.code_hash,
+            })
+        })
+        .unwrap_or(before)
+}
@@ -1,89 +1,744 @@
 use log::info;
 use pevm::execute_revm;
-use pevm::AccountBasic;
 use pevm::BlobExcessGasAndPrice;
-use pevm::EvmAccount;
-use pevm::InMemoryStorage;
-use pevm::PevmResult;
+use pevm::PevmTxExecutionResult;
 use pevm::PevmUserType;
-use pevm::CANCUN;
+use reth_chainspec::ChainSpec;
+use reth_primitives::constants::eip4844::DATA_GAS_PER_BLOB;
+use reth_primitives::constants::eip4844::MAX_DATA_GAS_PER_BLOCK;
+use reth_primitives::constants::MIN_TRANSACTION_GAS;
 use reth_primitives::revm::config::revm_spec_by_timestamp_after_merge;
-use reth_primitives::revm_primitives::EVMError;
+use reth_primitives::Account;
 use reth_primitives::Address;
+use reth_primitives::Bytes;
+use reth_primitives::SealedHeader;
 use reth_primitives::TransactionSigned;
+use reth_primitives::TransactionSignedEcRecovered;
+use reth_primitives::TxHash;
 use reth_primitives::U256;
 use reth_revm::primitives::SpecId;
 
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::num::NonZeroUsize;
-use std::sync::Arc;
-use std::thread;
+use std::time::Duration;
+use std::time::Instant;
 
-use reth_chainspec::HOLESKY;
+use reth_provider::AccountReader;
 use reth_provider::BlockReaderIdExt;
+use reth_provider::ChainSpecProvider;
 use reth_provider::StateProviderFactory;
 use reth_revm::database::StateProviderDatabase;
 use reth_revm::db::CacheDB;
 use reth_revm::interpreter::gas::ZERO;
+use reth_rpc_types::engine::PayloadAttributes;
+use reth_trie::HashedPostState;
 
+use crate::block::assemble_block;
+use crate::block::BuiltBlock;
+use crate::bundle::Bundle;
+use crate::bundle::BundleExclusionReason;
+use crate::bundle::ExcludedBundle;
+use crate::error::PbbError;
 use crate::lighthouse::BeaconEventsConfig;
-use crate::reth_db::reth_db_provider;
-use crate::utils::chain_spec;
-use crate::utils::{bytecode_to_evmcode, get_tx_env};
+use crate::ordering::order_by_sender;
+use crate::ordering::Ordering;
+use crate::payment::account_after;
+use crate::payment::BuilderPayment;
+use crate::receipts::receipts_from_pevm;
+use crate::recovery::recover_signers;
+use crate::report::BuildReport;
+use crate::reth::find_invalid_transactions;
+use crate::reth::RejectedTx;
+use crate::reth_db::RethProvider;
+use crate::selection::select_transactions;
+use crate::selection::BlockLimits;
+use crate::selection::ExcludedTx;
+use crate::selection::ExclusionReason;
+use crate::selection::Selection;
+use crate::state_root::apply_withdrawals;
+use crate::state_root::compute_state_root;
+use crate::state_root::hashed_state_from_cache_db;
+use crate::state_root::hashed_state_from_pevm;
+use crate::state_root::log_state_root_timing;
+use crate::storage::RethStorage;
+use crate::system_calls::apply_pre_block_calls;
+use crate::tx_env::tx_env;
+use crate::utils::pevm_spec_id;
+use crate::utils::retain_chain_transactions;
 
-pub async fn run_pevm(txs_signed: Vec<TransactionSigned>) -> PevmResult {
-    let provider = reth_db_provider();
-    let chain_spec = chain_spec();
+/// Transactions executed by pevm on top of a parent block
+pub struct PevmExecution {
+    pub block_env: pevm::BlockEnv,
+    /// State changed by the system calls run before the transactions
+    pub pre_block_changes: HashedPostState,
+    pub results: Vec<PevmTxExecutionResult>,
+    pub elapsed: Duration,
+}
+
+/// Number of times pevm reruns a block after pruning invalid or low paying transactions
+/// out of it, the reruns after pruning reverting bundles don't count
+const MAX_PRUNING_ROUNDS: usize = 3;
 
-    let beacon_client = BeaconEventsConfig::new();
-    let payload_attributes = beacon_client.run().await.unwrap().data.payload_attributes;
+/// How a block is built out of the transactions it is given
+#[derive(Debug, Clone, Default)]
+pub struct BuildOptions {
+    /// Pays the proposer from the builder coinbase instead of setting its fee recipient as coinbase
+    pub builder_payment: Option<BuilderPayment>,
+    /// Drops the reverting transactions that pay the coinbase less than this
+    pub min_reverted_tx_fee: Option<U256>,
+    /// Executes the transactions in the given order instead of ordering them by
+    /// sender nonce and tip, to replay an existing block
+    pub preserve_order: bool,
+    /// Gas limit of the block, the gas limit of the parent if unset
+    pub gas_limit: Option<u64>,
+    /// Extra data of the block header
+    pub extra_data: Bytes,
+}
+
+impl BuildOptions {
+    /// Returns the gas limit of the block built on top of `parent`
+    pub fn gas_limit(&self, parent: &SealedHeader) -> u64 {
+        self.gas_limit.unwrap_or(parent.gas_limit)
+    }
+}
+
+/// Waits for the next payload attributes and builds a block on top of the latest block
+pub async fn run_pevm(
+    provider: &RethProvider,
+    beacon_client: BeaconEventsConfig,
+    txs_signed: Vec<TransactionSigned>,
+    bundles: Vec<Bundle>,
+    concurrency_level: NonZeroUsize,
+    options: &BuildOptions,
+) -> Result<BuiltBlock, PbbError> {
+    let payload_attributes = beacon_client.run().await?.data.payload_attributes;
 
     let latest_block_header = provider
-        .latest_header()
-        .map_err(|_e| EVMError::Database(String::from("Error fetching latest sealed header")))
-        .unwrap()
-        .unwrap();
-    let latest_state = provider
-        .state_by_block_hash(latest_block_header.hash())
-        .map_err(|_| EVMError::Database(String::from("Error fetching latest state")))
-        .unwrap();
-    let state = Arc::new(StateProviderDatabase::new(latest_state));
-    let cache_db = CacheDB::new(Arc::clone(&state));
+        .latest_header()?
+        .ok_or(PbbError::MissingLatestHeader)?;
 
-    let accounts: HashMap<Address, EvmAccount> = cache_db
-        .accounts
-        .into_iter()
-        .map(|(addr, acc)| {
-            (
-                addr,
-                EvmAccount {
-                    basic: AccountBasic {
-                        balance: acc.info.balance,
-                        nonce: acc.info.nonce,
-                        code_hash: Some(acc.info.code_hash),
-                        code: Some(bytecode_to_evmcode(acc.info.code.unwrap())),
+    let txs = retain_chain_transactions(recover_signers(txs_signed), &provider.chain_spec());
+    build_block(
+        provider,
+        &latest_block_header,
+        &payload_attributes,
+        txs,
+        bundles,
+        concurrency_level,
+        options,
+    )
+}
+
+/// Transactions going into a block being built, and the ones left out of it
+#[derive(Debug, Default)]
+struct BlockContents {
+    /// Transactions of `bundles` in bundle order, followed by the mempool transactions
+    txs: Vec<TransactionSignedEcRecovered>,
+    bundles: Vec<Bundle>,
+    excluded: Vec<ExcludedTx>,
+    rejected: Vec<RejectedTx>,
+    excluded_bundles: Vec<ExcludedBundle>,
+}
+
+impl BlockContents {
+    /// Removes the bundle at `index` along with its transactions
+    fn exclude_bundle(&mut self, index: usize, reason: BundleExclusionReason) {
+        let bundle = self.bundles.remove(index);
+        info!("excluding bundle {}: {}", bundle.hash(), reason);
+        self.txs.retain(|tx| !bundle.contains(&tx.hash()));
+        self.excluded_bundles.push(ExcludedBundle {
+            hash: bundle.hash(),
+            reason,
+        });
+    }
+
+    /// Removes the transactions the EVM rejected, and the whole bundle of the ones in a bundle
+    fn reject(&mut self, rejected: Vec<RejectedTx>) {
+        for rejected_tx in rejected {
+            // an earlier rejected transaction may have taken out its bundle already
+            if !self.txs.iter().any(|tx| tx.hash() == rejected_tx.hash) {
+                continue;
+            }
+            match self
+                .bundles
+                .iter()
+                .position(|bundle| bundle.contains(&rejected_tx.hash))
+            {
+                Some(index) => self.exclude_bundle(
+                    index,
+                    BundleExclusionReason::InvalidTransaction {
+                        tx_hash: rejected_tx.hash,
+                        error: rejected_tx.error.to_string(),
                     },
-                    storage: acc.storage.into_iter().collect(),
-                },
+                ),
+                None => {
+                    self.txs.retain(|tx| tx.hash() != rejected_tx.hash);
+                    self.rejected.push(rejected_tx);
+                }
+            }
+        }
+    }
+
+    /// Returns true if the transaction belongs to one of the bundles of the block
+    fn in_bundle(&self, tx_hash: &TxHash) -> bool {
+        self.bundles.iter().any(|bundle| bundle.contains(tx_hash))
+    }
+
+    /// Returns the next nonce of every sender of a bundle, once the bundles executed
+    fn next_nonces(&self) -> HashMap<Address, u64> {
+        let mut next_nonces: HashMap<Address, u64> = HashMap::new();
+        for tx in self.bundles.iter().flat_map(|bundle| &bundle.txs) {
+            let next_nonce = next_nonces.entry(tx.signer()).or_default();
+            *next_nonce = (*next_nonce).max(tx.nonce() + 1);
+        }
+        next_nonces
+    }
+
+    /// Returns true if the transaction was pruned after the EVM rejected it or it
+    /// reverted paying too little
+    fn pruned(&self, tx_hash: &TxHash) -> bool {
+        self.rejected.iter().any(|tx| tx.hash == *tx_hash)
+            || self.excluded.iter().any(|tx| {
+                tx.hash == *tx_hash && matches!(tx.reason, ExclusionReason::RevertedLowFee { .. })
+            })
+    }
+
+    /// Returns `rebuilt`, filled again without the orders pruned from this block, along
+    /// with what was pruned from this block so that it is still reported
+    fn rebuilt(self, mut rebuilt: BlockContents) -> BlockContents {
+        rebuilt.excluded.extend(
+            self.excluded
+                .into_iter()
+                .filter(|tx| matches!(tx.reason, ExclusionReason::RevertedLowFee { .. })),
+        );
+        rebuilt.rejected.extend(self.rejected);
+        rebuilt.excluded_bundles.extend(self.excluded_bundles);
+        rebuilt
+    }
+}
+
+/// Builds a block on top of `parent` out of `bundles`, at the top of the block in the
+/// given order, and the transactions of `txs_signed` that fit in its gas and blob
+/// limits, ordered by sender nonce and effective tip. The transactions the EVM rejects
+/// are pruned and the block rebuilt without them. The withdrawals of
+/// `payload_attributes` are credited after the last transaction.
+///
+/// A bundle is included only if all of its transactions that aren't allowed to revert
+/// succeed, otherwise the block is rebuilt without any of its transactions. The
+/// transactions left out for a nonce taken by a pruned bundle are then ordered again.
+///
+/// With a `builder_payment` whose address differs from the suggested fee recipient,
+/// the builder is the coinbase and a transfer of its profit to the fee recipient is
+/// appended. The block is then executed again so the payment runs last, on top of
+/// every other transaction.
+pub fn build_block(
+    provider: &RethProvider,
+    parent: &SealedHeader,
+    payload_attributes: &PayloadAttributes,
+    txs_signed: Vec<TransactionSignedEcRecovered>,
+    bundles: Vec<Bundle>,
+    concurrency_level: NonZeroUsize,
+    options: &BuildOptions,
+) -> Result<BuiltBlock, PbbError> {
+    let builder_payment = options
+        .builder_payment
+        .as_ref()
+        .filter(|payment| payment.address() != payload_attributes.suggested_fee_recipient);
+    let coinbase = builder_payment.map_or(
+        payload_attributes.suggested_fee_recipient,
+        BuilderPayment::address,
+    );
+
+    let gas_limit = options.gas_limit(parent);
+    let (block_env, _) = next_block_env(
+        &provider.chain_spec(),
+        parent,
+        payload_attributes,
+        coinbase,
+        gas_limit,
+    );
+    let reserved_gas = builder_payment.map_or(0, |_| MIN_TRANSACTION_GAS);
+    let parent_state = provider.state_by_block_hash(parent.hash())?;
+
+    let mut bundles = bundles;
+    let mut txs_signed = txs_signed;
+    let mut pruned: Option<BlockContents> = None;
+    let (mut contents, mut execution) = loop {
+        let mut contents = fill_block(
+            &parent_state,
+            &block_env,
+            reserved_gas,
+            bundles,
+            txs_signed.clone(),
+            options,
+        )?;
+        let bundle_count = contents.bundles.len();
+        let execution = execute_pruned(
+            provider,
+            parent,
+            payload_attributes,
+            coinbase,
+            &mut contents,
+            concurrency_level,
+            options,
+        )?;
+        let mut contents = match pruned.take() {
+            Some(pruned) => pruned.rebuilt(contents),
+            None => contents,
+        };
+
+        // a bundle pruned during execution frees the nonces it took, the transactions
+        // left out for them are ordered again along with the remaining bundles
+        let frees_nonces = contents.bundles.len() < bundle_count
+            && contents
+                .excluded
+                .iter()
+                .any(|tx| matches!(tx.reason, ExclusionReason::NonceTakenByBundle { .. }));
+        if !frees_nonces {
+            break (contents, execution);
+        }
+        info!("rebuilding with the nonces freed by the pruned bundles");
+        bundles = std::mem::take(&mut contents.bundles);
+        txs_signed.retain(|tx| !contents.pruned(&tx.hash()));
+        pruned = Some(contents);
+    };
+
+    let mut proposer_payment = None;
+    if let Some(builder_payment) = builder_payment {
+        let (payment, value) = proposer_payment_transaction(
+            provider,
+            parent,
+            payload_attributes,
+            &execution,
+            builder_payment,
+        )?;
+        info!(
+            "paying {} to the proposer {} with tx {}",
+            value,
+            payload_attributes.suggested_fee_recipient,
+            payment.hash()
+        );
+        contents
+            .txs
+            .push(TransactionSignedEcRecovered::from_signed_transaction(
+                payment,
+                builder_payment.address(),
+            ));
+        proposer_payment = Some(value);
+        execution = execute_pevm_with_coinbase(
+            provider,
+            parent,
+            payload_attributes,
+            coinbase,
+            gas_limit,
+            &contents.txs,
+            concurrency_level,
+        )?;
+    }
+
+    let coinbase_before = provider
+        .state_by_block_hash(parent.hash())?
+        .basic_account(coinbase)?;
+    let senders: Vec<Address> = contents.txs.iter().map(|tx| tx.signer()).collect();
+    let txs: Vec<TransactionSigned> = contents
+        .txs
+        .into_iter()
+        .map(TransactionSignedEcRecovered::into_signed)
+        .collect();
+    let report = BuildReport::from_execution(
+        &txs,
+        &contents.bundles,
+        &execution,
+        coinbase_before,
+        proposer_payment,
+    );
+
+    let mut hashed_state = execution.pre_block_changes.clone();
+    hashed_state.extend(hashed_state_from_pevm(&execution.results));
+    if let Some(withdrawals) = &payload_attributes.withdrawals {
+        let parent_state = provider.state_by_block_hash(parent.hash())?;
+        apply_withdrawals(&mut hashed_state, &parent_state, withdrawals)?;
+    }
+    let state_root = compute_state_root(provider, parent, &hashed_state)?;
+    log_state_root_timing(execution.elapsed, &state_root);
+
+    let receipts = receipts_from_pevm(&txs, &execution.results);
+    let block = assemble_block(
+        parent,
+        payload_attributes,
+        &execution.block_env,
+        txs,
+        &receipts,
+        state_root.state_root,
+        options.extra_data.clone(),
+    )?;
+    info!("built block {} with hash {}", block.number, block.hash());
+    Ok(BuiltBlock {
+        block,
+        senders,
+        receipts,
+        report,
+        bundles: contents.bundles,
+        excluded: contents.excluded,
+        rejected: contents.rejected,
+        excluded_bundles: contents.excluded_bundles,
+    })
+}
+
+/// Places `bundles` at the top of a block executed in `block_env`, in the given order,
+/// followed by the transactions of `txs_signed` that fit in what they leave of its
+/// gas and blob limits, ordered by sender nonce and effective tip unless the options
+/// preserve their order
+fn fill_block(
+    parent_state: &impl AccountReader,
+    block_env: &pevm::BlockEnv,
+    reserved_gas: u64,
+    bundles: Vec<Bundle>,
+    txs_signed: Vec<TransactionSignedEcRecovered>,
+    options: &BuildOptions,
+) -> Result<BlockContents, PbbError> {
+    let mut contents = BlockContents::default();
+    let mut bundles_gas = 0;
+    let mut bundles_blobs = 0;
+    let max_blob_count = MAX_DATA_GAS_PER_BLOCK / DATA_GAS_PER_BLOB;
+    let mut remaining_gas = block_env
+        .gas_limit
+        .saturating_to::<u64>()
+        .saturating_sub(reserved_gas);
+    for bundle in bundles {
+        let duplicate = bundle
+            .txs
+            .iter()
+            .find(|tx| contents.in_bundle(&tx.hash()))
+            .map(|tx| tx.hash());
+        let checked = bundle
+            .check_target(
+                block_env.number.saturating_to(),
+                block_env.timestamp.saturating_to(),
             )
-        })
+            .and_then(|()| match duplicate {
+                Some(tx_hash) => Err(BundleExclusionReason::DuplicateTransaction { tx_hash }),
+                None => Ok(()),
+            })
+            .and_then(|()| {
+                if bundle.gas_limit() > remaining_gas {
+                    Err(BundleExclusionReason::GasLimit {
+                        gas_limit: bundle.gas_limit(),
+                        remaining: remaining_gas,
+                    })
+                } else if bundle.blob_count() > max_blob_count - bundles_blobs {
+                    Err(BundleExclusionReason::BlobLimit {
+                        blobs: bundle.blob_count(),
+                        remaining: max_blob_count - bundles_blobs,
+                    })
+                } else {
+                    Ok(())
+                }
+            });
+        match checked {
+            Ok(()) => {
+                remaining_gas -= bundle.gas_limit();
+                bundles_gas += bundle.gas_limit();
+                bundles_blobs += bundle.blob_count();
+                contents.txs.extend(bundle.txs.iter().cloned());
+                contents.bundles.push(bundle);
+            }
+            Err(reason) => contents.excluded_bundles.push(ExcludedBundle {
+                hash: bundle.hash(),
+                reason,
+            }),
+        }
+    }
+
+    // the transactions of the bundles are only included as part of them
+    let txs_signed: Vec<TransactionSignedEcRecovered> = txs_signed
+        .into_iter()
+        .filter(|tx| !contents.in_bundle(&tx.hash()))
+        .collect();
+    let txs_signed = if options.preserve_order {
+        txs_signed
+    } else {
+        let base_fee = block_env.basefee.saturating_to::<u64>();
+        let Ordering { ordered, excluded } =
+            order_by_sender(txs_signed, parent_state, &contents.next_nonces(), base_fee)?;
+        contents.excluded.extend(excluded);
+        ordered
+    };
+    let Selection { selected, excluded } = select_transactions(
+        txs_signed,
+        &BlockLimits::from_block_env(block_env, reserved_gas + bundles_gas, bundles_blobs),
+    );
+    contents.txs.extend(selected);
+    contents.excluded.extend(excluded);
+    if !contents.excluded.is_empty() {
+        let deferred = contents
+            .excluded
+            .iter()
+            .filter(|tx| tx.reason.is_deferred())
+            .count();
+        info!(
+            "{} txs deferred to a later block, {} dropped",
+            deferred,
+            contents.excluded.len() - deferred
+        );
+    }
+    Ok(contents)
+}
+
+/// Executes the transactions of `contents` with pevm, pruning from it the transactions
+/// the EVM rejects, the bundles that revert and, with `min_reverted_tx_fee` set in
+/// `options`, the reverting transactions that pay the coinbase less than that
+fn execute_pruned(
+    provider: &RethProvider,
+    parent: &SealedHeader,
+    payload_attributes: &PayloadAttributes,
+    coinbase: Address,
+    contents: &mut BlockContents,
+    concurrency_level: NonZeroUsize,
+    options: &BuildOptions,
+) -> Result<PevmExecution, PbbError> {
+    let gas_limit = options.gas_limit(parent);
+    prune_until_valid(
+        contents,
+        options.min_reverted_tx_fee,
+        |txs| {
+            execute_pevm_with_coinbase(
+                provider,
+                parent,
+                payload_attributes,
+                coinbase,
+                gas_limit,
+                txs,
+                concurrency_level,
+            )
+        },
+        |txs| {
+            // pevm fails the whole batch without telling which transaction is invalid,
+            // the sequential executor finds all of them in a single pass
+            find_invalid_transactions(
+                provider,
+                parent,
+                payload_attributes,
+                coinbase,
+                gas_limit,
+                txs.to_vec(),
+            )
+        },
+    )
+}
+
+/// Whether a transaction succeeded and the gas it used
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct TxOutcome {
+    success: bool,
+    gas_used: u64,
+}
+
+/// Execution of the transactions of a block, as far as pruning them is concerned
+trait BlockOutcome {
+    /// Base fee of the block the transactions executed in
+    fn base_fee(&self) -> u64;
+
+    /// Returns the outcome of every transaction, in order
+    fn tx_outcomes(&self) -> Vec<TxOutcome>;
+}
+
+impl BlockOutcome for PevmExecution {
+    fn base_fee(&self) -> u64 {
+        self.block_env.basefee.saturating_to()
+    }
+
+    fn tx_outcomes(&self) -> Vec<TxOutcome> {
+        let mut previous_cumulative_gas_used = 0;
+        self.results
+            .iter()
+            .map(|result| {
+                let cumulative_gas_used = result.receipt.cumulative_gas_used as u64;
+                let gas_used = cumulative_gas_used - previous_cumulative_gas_used;
+                previous_cumulative_gas_used = cumulative_gas_used;
+                TxOutcome {
+                    success: result.receipt.status.coerce_status(),
+                    gas_used,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Runs `execute` on the transactions of `contents` until no more of them have to be
+/// pruned, finding the ones to prune with `find_invalid` when `execute` fails.
+///
+/// Bundles that revert are pruned as long as there are some left, every round drops at
+/// least one of them. Invalid and low paying transactions are pruned for at most
+/// `MAX_PRUNING_ROUNDS` rounds.
+fn prune_until_valid<E: BlockOutcome>(
+    contents: &mut BlockContents,
+    min_reverted_tx_fee: Option<U256>,
+    mut execute: impl FnMut(&[TransactionSignedEcRecovered]) -> Result<E, PbbError>,
+    mut find_invalid: impl FnMut(&[TransactionSignedEcRecovered]) -> Result<Vec<RejectedTx>, PbbError>,
+) -> Result<E, PbbError> {
+    let mut pruning_rounds = 0;
+    loop {
+        let execution = match execute(&contents.txs) {
+            Ok(execution) => execution,
+            Err(e) if pruning_rounds < MAX_PRUNING_ROUNDS => {
+                pruning_rounds += 1;
+                let invalid = find_invalid(&contents.txs)?;
+                if invalid.is_empty() {
+                    return Err(e);
+                }
+                info!("pruning {} invalid txs", invalid.len());
+                contents.reject(invalid);
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        let outcomes = execution.tx_outcomes();
+
+        let reverted = reverted_bundles(contents, &outcomes);
+        if !reverted.is_empty() {
+            for (index, tx_hash) in reverted.into_iter().rev() {
+                contents.exclude_bundle(index, BundleExclusionReason::Reverted { tx_hash });
+            }
+            continue;
+        }
+
+        let mut low_paying = match min_reverted_tx_fee {
+            Some(min_fee) if pruning_rounds < MAX_PRUNING_ROUNDS => {
+                low_paying_reverts(&contents.txs, &outcomes, execution.base_fee(), min_fee)
+            }
+            _ => Vec::new(),
+        };
+        // revert-allowed transactions of bundles stay to keep the bundle as sent
+        low_paying.retain(|tx| !contents.in_bundle(&tx.hash));
+        if low_paying.is_empty() {
+            return Ok(execution);
+        }
+        pruning_rounds += 1;
+        info!("pruning {} low paying reverting txs", low_paying.len());
+        let hashes: HashSet<TxHash> = low_paying.iter().map(|tx| tx.hash).collect();
+        contents.txs.retain(|tx| !hashes.contains(&tx.hash()));
+        contents.excluded.extend(low_paying);
+    }
+}
+
+/// Returns the index of every bundle with a transaction that reverted without being
+/// allowed to, along with that transaction
+fn reverted_bundles(contents: &BlockContents, outcomes: &[TxOutcome]) -> Vec<(usize, TxHash)> {
+    let succeeded: HashMap<TxHash, bool> = contents
+        .txs
+        .iter()
+        .zip(outcomes)
+        .map(|(tx, outcome)| (tx.hash(), outcome.success))
         .collect();
-    let block_hashes: pevm::AHashMap<U256, reth_primitives::B256> =
-        cache_db.block_hashes.into_iter().collect();
-    let pevm_storage = InMemoryStorage::new(accounts, block_hashes);
-    let holesky = HOLESKY.clone();
+    contents
+        .bundles
+        .iter()
+        .enumerate()
+        .filter_map(|(index, bundle)| {
+            bundle
+                .txs
+                .iter()
+                .map(|tx| tx.hash())
+                .find(|tx_hash| {
+                    !bundle.can_revert(tx_hash) && succeeded.get(tx_hash) == Some(&false)
+                })
+                .map(|tx_hash| (index, tx_hash))
+        })
+        .collect()
+}
+
+/// Returns the transactions that reverted while paying the coinbase less than `min_fee`
+fn low_paying_reverts(
+    txs_signed: &[TransactionSignedEcRecovered],
+    outcomes: &[TxOutcome],
+    base_fee: u64,
+    min_fee: U256,
+) -> Vec<ExcludedTx> {
+    txs_signed
+        .iter()
+        .zip(outcomes)
+        .filter(|(_, outcome)| !outcome.success)
+        .filter_map(|(tx, outcome)| {
+            let tip = tx.effective_tip_per_gas(Some(base_fee)).unwrap_or_default();
+            let fee = U256::from(outcome.gas_used) * U256::from(tip);
+            (fee < min_fee).then(|| ExcludedTx {
+                hash: tx.hash(),
+                reason: ExclusionReason::RevertedLowFee { fee },
+            })
+        })
+        .collect()
+}
 
-    let spec_id = revm_spec_by_timestamp_after_merge(&chain_spec, payload_attributes.timestamp);
+/// Signs the payment of the builder profit measured in `execution` to the proposer
+fn proposer_payment_transaction(
+    provider: &RethProvider,
+    parent: &SealedHeader,
+    payload_attributes: &PayloadAttributes,
+    execution: &PevmExecution,
+    builder_payment: &BuilderPayment,
+) -> Result<(TransactionSigned, U256), PbbError> {
+    let builder = builder_payment.address();
+    let before = provider
+        .state_by_block_hash(parent.hash())?
+        .basic_account(builder)?;
+    let after = account_after(builder, before, &execution.results);
 
-    let base_fee = latest_block_header
+    let balance = |account: Option<Account>| account.map(|a| a.balance).unwrap_or_default();
+    let coinbase_delta = balance(after).saturating_sub(balance(before));
+    let base_fee = execution.block_env.basefee.saturating_to::<u64>();
+    let value = builder_payment.payment_value(coinbase_delta, base_fee)?;
+
+    let payment = builder_payment.payment_transaction(
+        provider.chain_spec().chain.id(),
+        after.map(|a| a.nonce).unwrap_or_default(),
+        base_fee,
+        payload_attributes.suggested_fee_recipient,
+        value,
+    )?;
+    Ok((payment, value))
+}
+
+/// Executes `txs_signed` in parallel on top of the state of `parent`
+pub fn execute_pevm(
+    provider: &RethProvider,
+    parent: &SealedHeader,
+    payload_attributes: &PayloadAttributes,
+    txs_signed: &[TransactionSignedEcRecovered],
+    concurrency_level: NonZeroUsize,
+) -> Result<PevmExecution, PbbError> {
+    execute_pevm_with_coinbase(
+        provider,
+        parent,
+        payload_attributes,
+        payload_attributes.suggested_fee_recipient,
+        parent.gas_limit,
+        txs_signed,
+        concurrency_level,
+    )
+}
+
+/// Returns the environment of the block built on top of `parent` with `payload_attributes`,
+/// along with the spec it is executed in
+pub fn next_block_env(
+    chain_spec: &ChainSpec,
+    parent: &SealedHeader,
+    payload_attributes: &PayloadAttributes,
+    coinbase: Address,
+    gas_limit: u64,
+) -> (pevm::BlockEnv, SpecId) {
+    let spec_id = revm_spec_by_timestamp_after_merge(chain_spec, payload_attributes.timestamp);
+
+    let base_fee = parent
         .header()
         .next_block_base_fee(chain_spec.base_fee_params_at_timestamp(payload_attributes.timestamp));
 
-    let blob_excess_gas_and_price = latest_block_header
+    let blob_excess_gas_and_price = parent
         .header()
         .next_block_excess_blob_gas()
         .or_else(|| {
-            if spec_id == SpecId::CANCUN {
+            if spec_id.is_enabled_in(SpecId::CANCUN) {
                 // default excess blob gas is zero
                 Some(0)
             } else {
@@ -93,41 +748,281 @@ pub async fn run_pevm(txs_signed: Vec<TransactionSigned>) -> PevmResult {
         .map(BlobExcessGasAndPrice::new);
 
     let block_env = pevm::BlockEnv {
-        number: U256::from(latest_block_header.number + 1),
+        number: U256::from(parent.number + 1),
         timestamp: U256::from(payload_attributes.timestamp),
-        coinbase: payload_attributes.suggested_fee_recipient,
-        gas_limit: U256::from(latest_block_header.gas_limit),
+        coinbase,
+        gas_limit: U256::from(gas_limit),
         basefee: base_fee.map(U256::from).unwrap_or_default(),
         difficulty: U256::from(ZERO),
         prevrandao: Some(payload_attributes.prev_randao),
         blob_excess_gas_and_price,
     };
+    (block_env, spec_id)
+}
 
-    let transactions_envs: Vec<pevm::TxEnv> = txs_signed
-        .into_iter()
-        .map(|tx_signed| get_tx_env(tx_signed))
-        .collect();
+/// Executes `txs_signed` in parallel on top of the state of `parent`, crediting the fees to `coinbase`
+/// in a block of `gas_limit`
+pub fn execute_pevm_with_coinbase(
+    provider: &RethProvider,
+    parent: &SealedHeader,
+    payload_attributes: &PayloadAttributes,
+    coinbase: Address,
+    gas_limit: u64,
+    txs_signed: &[TransactionSignedEcRecovered],
+    concurrency_level: NonZeroUsize,
+) -> Result<PevmExecution, PbbError> {
+    let chain_spec = provider.chain_spec();
+
+    let parent_state = provider.state_by_block_hash(parent.hash())?;
+    let mut pre_block_state = CacheDB::new(StateProviderDatabase::new(parent_state));
+    apply_pre_block_calls(
+        &chain_spec,
+        parent,
+        payload_attributes,
+        &mut pre_block_state,
+    )?;
+    let pre_block_changes = hashed_state_from_cache_db(&pre_block_state);
+    let pevm_storage = RethStorage::with_changes(pre_block_state)?;
+
+    let (block_env, spec_id) =
+        next_block_env(&chain_spec, parent, payload_attributes, coinbase, gas_limit);
+    let pevm_spec_id = pevm_spec_id(spec_id)?;
 
-    let concurrency_level = thread::available_parallelism().unwrap_or(NonZeroUsize::MIN);
+    let transactions_envs: Vec<pevm::TxEnv> = txs_signed.iter().map(tx_env).collect();
 
+    let execution_start = Instant::now();
     let pevm_result = execute_revm(
         pevm_storage,
-        holesky.chain,
-        CANCUN,
-        block_env,
+        chain_spec.chain,
+        pevm_spec_id,
+        block_env.clone(),
         transactions_envs,
         concurrency_level,
         PevmUserType::BlockBuilder,
     );
 
+    let execution_elapsed = execution_start.elapsed();
+
     match pevm_result {
-        Ok(_) => {
-            info!("txs executed successfully");
-            pevm_result
+        Ok(results) => {
+            info!("txs executed successfully in {:?}", execution_elapsed);
+            Ok(PevmExecution {
+                block_env,
+                pre_block_changes,
+                results,
+                elapsed: execution_elapsed,
+            })
         }
         Err(e) => {
             info!("Error executing txs: {:?}", e);
-            Err(e)
+            Err(PbbError::Pevm(e))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reth_revm::primitives::EVMError;
+    use reth_revm::primitives::InvalidTransaction;
+
+    use super::*;
+    use crate::test_utils::tx;
+    use crate::test_utils::MockAccounts;
+    use crate::test_utils::TRANSFER_GAS;
+
+    const ALICE: Address = Address::repeat_byte(0xa1);
+    const BOB: Address = Address::repeat_byte(0xb0);
+    const BASE_FEE: u64 = 10;
+
+    fn contents_with(bundles: Vec<Bundle>) -> BlockContents {
+        BlockContents {
+            txs: bundles
+                .iter()
+                .flat_map(|bundle| bundle.txs.clone())
+                .collect(),
+            bundles,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn pruned_bundle_frees_the_nonces_it_took() {
+        let alice_bundle = Bundle::new(vec![tx(ALICE, 0, TRANSFER_GAS, 100, 1)], 1, HashSet::new());
+        let bob_bundle = Bundle::new(vec![tx(BOB, 0, TRANSFER_GAS, 100, 1)], 1, HashSet::new());
+        let mut contents = contents_with(vec![alice_bundle.clone(), bob_bundle]);
+        let mempool = vec![tx(ALICE, 0, TRANSFER_GAS, 100, 2)];
+
+        let ordering = order_by_sender(
+            mempool.clone(),
+            &MockAccounts::default(),
+            &contents.next_nonces(),
+            BASE_FEE,
+        )
+        .unwrap();
+        assert!(ordering.ordered.is_empty());
+        assert_eq!(
+            ordering.excluded[0].reason,
+            ExclusionReason::NonceTakenByBundle {
+                nonce: 0,
+                next_nonce: 1,
+            }
+        );
+
+        let tx_hash = alice_bundle.txs[0].hash();
+        contents.exclude_bundle(0, BundleExclusionReason::Reverted { tx_hash });
+        assert_eq!(contents.next_nonces(), HashMap::from([(BOB, 1)]));
+        let ordering = order_by_sender(
+            mempool.clone(),
+            &MockAccounts::default(),
+            &contents.next_nonces(),
+            BASE_FEE,
+        )
+        .unwrap();
+        assert_eq!(ordering.ordered[0].hash(), mempool[0].hash());
+        assert!(ordering.excluded.is_empty());
+    }
+
+    #[test]
+    fn rebuilt_contents_keep_what_was_pruned() {
+        let bundle = Bundle::new(vec![tx(ALICE, 0, TRANSFER_GAS, 100, 1)], 1, HashSet::new());
+        let low_paying = tx(BOB, 0, TRANSFER_GAS, 100, 1);
+        let taken = tx(ALICE, 0, TRANSFER_GAS, 100, 2);
+        let mut pruned = contents_with(vec![bundle.clone()]);
+        pruned.exclude_bundle(
+            0,
+            BundleExclusionReason::Reverted {
+                tx_hash: bundle.txs[0].hash(),
+            },
+        );
+        pruned.excluded = vec![
+            ExcludedTx {
+                hash: low_paying.hash(),
+                reason: ExclusionReason::RevertedLowFee { fee: U256::ZERO },
+            },
+            ExcludedTx {
+                hash: taken.hash(),
+                reason: ExclusionReason::NonceTakenByBundle {
+                    nonce: 0,
+                    next_nonce: 1,
+                },
+            },
+        ];
+        assert!(pruned.pruned(&low_paying.hash()));
+        assert!(!pruned.pruned(&taken.hash()));
+
+        let rebuilt = pruned.rebuilt(BlockContents {
+            txs: vec![taken],
+            ..Default::default()
+        });
+        assert_eq!(rebuilt.txs.len(), 1);
+        assert_eq!(
+            rebuilt.excluded,
+            vec![ExcludedTx {
+                hash: low_paying.hash(),
+                reason: ExclusionReason::RevertedLowFee { fee: U256::ZERO },
+            }]
+        );
+        assert_eq!(rebuilt.excluded_bundles.len(), 1);
+    }
+
+    /// Outcome of a mock execution of a block
+    struct MockExecution(Vec<TxOutcome>);
+
+    impl BlockOutcome for MockExecution {
+        fn base_fee(&self) -> u64 {
+            BASE_FEE
+        }
+
+        fn tx_outcomes(&self) -> Vec<TxOutcome> {
+            self.0.clone()
         }
     }
+
+    #[test]
+    fn reverting_bundles_leave_the_pruning_rounds_to_invalid_txs() {
+        const CAROL: Address = Address::repeat_byte(0xc0);
+        const DAVE: Address = Address::repeat_byte(0xd0);
+        let bundle_senders = [ALICE, BOB, CAROL];
+        let bundles: Vec<Bundle> = bundle_senders
+            .iter()
+            .map(|sender| {
+                Bundle::new(
+                    vec![tx(*sender, 0, TRANSFER_GAS, 100, 1)],
+                    1,
+                    HashSet::new(),
+                )
+            })
+            .collect();
+        let mut contents = contents_with(bundles);
+        // only the bundle of carol funds dave enough to pay for the gas of its transaction
+        let unfunded = tx(DAVE, 0, TRANSFER_GAS, 100, 1);
+        contents.txs.push(unfunded.clone());
+
+        // the bundles all go after the same opportunity, the first one to run reverts
+        let execute = |txs: &[TransactionSignedEcRecovered]| -> Result<MockExecution, PbbError> {
+            let funded = txs.iter().any(|tx| tx.signer() == CAROL);
+            if !funded && txs.iter().any(|tx| tx.hash() == unfunded.hash()) {
+                return Err(PbbError::Execution("lack of funds".into()));
+            }
+            Ok(MockExecution(
+                txs.iter()
+                    .enumerate()
+                    .map(|(index, tx)| TxOutcome {
+                        success: index > 0 || !bundle_senders.contains(&tx.signer()),
+                        gas_used: TRANSFER_GAS,
+                    })
+                    .collect(),
+            ))
+        };
+        let find_invalid =
+            |txs: &[TransactionSignedEcRecovered]| -> Result<Vec<RejectedTx>, PbbError> {
+                Ok(txs
+                    .iter()
+                    .filter(|tx| tx.hash() == unfunded.hash())
+                    .map(|tx| RejectedTx {
+                        hash: tx.hash(),
+                        error: EVMError::Transaction(InvalidTransaction::NonceTooLow {
+                            tx: 0,
+                            state: 1,
+                        }),
+                    })
+                    .collect())
+            };
+
+        let execution = prune_until_valid(&mut contents, None, execute, find_invalid).unwrap();
+        assert!(execution.0.is_empty());
+        assert!(contents.txs.is_empty());
+        assert_eq!(contents.excluded_bundles.len(), 3);
+        assert_eq!(contents.rejected.len(), 1);
+        assert_eq!(contents.rejected[0].hash, unfunded.hash());
+    }
+
+    #[test]
+    fn filled_blocks_leave_room_for_the_payment() {
+        let gas_limit = 3 * TRANSFER_GAS;
+        let block_env = pevm::BlockEnv {
+            number: U256::from(1),
+            gas_limit: U256::from(gas_limit),
+            basefee: U256::from(BASE_FEE),
+            ..Default::default()
+        };
+        let bundle = Bundle::new(vec![tx(ALICE, 0, TRANSFER_GAS, 100, 1)], 1, HashSet::new());
+        let mempool = vec![
+            tx(BOB, 0, TRANSFER_GAS, 100, 1),
+            tx(BOB, 1, TRANSFER_GAS, 100, 1),
+        ];
+
+        let contents = fill_block(
+            &MockAccounts::default(),
+            &block_env,
+            MIN_TRANSACTION_GAS,
+            vec![bundle],
+            mempool,
+            &BuildOptions::default(),
+        )
+        .unwrap();
+        let used: u64 = contents.txs.iter().map(|tx| tx.gas_limit()).sum();
+        assert_eq!(used + MIN_TRANSACTION_GAS, gas_limit);
+        assert_eq!(contents.excluded.len(), 1);
+    }
 }
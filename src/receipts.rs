@@ -0,0 +1,350 @@
+use std::path::Path;
+
+use pevm::PevmTxExecutionResult;
+use reth_primitives::Address;
+use reth_primitives::Bloom;
+use reth_primitives::Bytes;
+use reth_primitives::Log;
+use reth_primitives::Receipt;
+use reth_primitives::ReceiptWithBloom;
+use reth_primitives::SealedBlock;
+use reth_primitives::TransactionSigned;
+use reth_primitives::B256;
+use reth_primitives::U256;
+use reth_revm::primitives::calc_blob_gasprice;
+use reth_revm::primitives::ExecutionResult;
+use serde::Serialize;
+
+use crate::error::PbbError;
+
+/// Builds the receipts of a pevm execution, one per transaction.
+pub fn receipts_from_pevm(
+    transactions: &[TransactionSigned],
+    results: &[PevmTxExecutionResult],
+) -> Vec<ReceiptWithBloom> {
+    transactions
+        .iter()
+        .zip(results)
+        .map(|(tx, result)| {
+            Receipt {
+                tx_type: tx.tx_type(),
+                success: result.receipt.status.coerce_status(),
+                cumulative_gas_used: result.receipt.cumulative_gas_used as u64,
+                logs: result.receipt.logs.clone(),
+            }
+            .with_bloom()
+        })
+        .collect()
+}
+
+/// Builds the receipt of a transaction executed by the sequential reth executor.
+pub fn receipt_from_reth(
+    tx: &TransactionSigned,
+    result: &ExecutionResult,
+    cumulative_gas_used: u64,
+) -> ReceiptWithBloom {
+    Receipt {
+        tx_type: tx.tx_type(),
+        success: result.is_success(),
+        cumulative_gas_used,
+        logs: result.logs().to_vec(),
+    }
+    .with_bloom()
+}
+
+/// Returns the block level logs bloom, the union of all the receipt blooms
+pub fn block_logs_bloom(receipts: &[ReceiptWithBloom]) -> Bloom {
+    let mut bloom = Bloom::ZERO;
+    for receipt in receipts {
+        bloom.accrue_bloom(&receipt.bloom);
+    }
+    bloom
+}
+
+/// Receipt in the shape returned by `eth_getBlockReceipts`
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RpcReceipt {
+    #[serde(rename = "type")]
+    pub tx_type: U256,
+    pub status: U256,
+    pub cumulative_gas_used: U256,
+    pub logs: Vec<RpcLog>,
+    pub logs_bloom: Bloom,
+    pub transaction_hash: B256,
+    pub transaction_index: U256,
+    pub block_hash: B256,
+    pub block_number: U256,
+    pub gas_used: U256,
+    pub effective_gas_price: U256,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub blob_gas_used: Option<U256>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub blob_gas_price: Option<U256>,
+    pub from: Address,
+    pub to: Option<Address>,
+    pub contract_address: Option<Address>,
+}
+
+/// Log in the shape returned by `eth_getBlockReceipts`
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RpcLog {
+    pub address: Address,
+    pub topics: Vec<B256>,
+    pub data: Bytes,
+    pub block_hash: B256,
+    pub block_number: U256,
+    pub transaction_hash: B256,
+    pub transaction_index: U256,
+    pub log_index: U256,
+    pub removed: bool,
+}
+
+/// Converts the receipts of `block` into their `eth_getBlockReceipts` representation.
+///
+/// `senders` and `receipts` must hold one entry per transaction of the block, in the
+/// same order.
+pub fn block_receipts_json(
+    block: &SealedBlock,
+    senders: &[Address],
+    receipts: &[ReceiptWithBloom],
+) -> Vec<RpcReceipt> {
+    let base_fee = block.base_fee_per_gas;
+    let blob_gas_price = block
+        .excess_blob_gas
+        .map(|excess| U256::from(calc_blob_gasprice(excess)));
+
+    let mut log_index = 0;
+    let mut previous_cumulative_gas_used = 0;
+
+    block
+        .body
+        .iter()
+        .zip(senders)
+        .zip(receipts)
+        .enumerate()
+        .map(|(index, ((tx, &from), receipt))| {
+            let gas_used = receipt.receipt.cumulative_gas_used - previous_cumulative_gas_used;
+            previous_cumulative_gas_used = receipt.receipt.cumulative_gas_used;
+
+            let to = tx.to();
+            let contract_address = to.is_none().then(|| from.create(tx.nonce()));
+
+            let logs = receipt
+                .receipt
+                .logs
+                .iter()
+                .map(|log| {
+                    let rpc_log = to_rpc_log(log, block, tx.hash(), index, log_index);
+                    log_index += 1;
+                    rpc_log
+                })
+                .collect();
+
+            RpcReceipt {
+                tx_type: U256::from(tx.tx_type() as u8),
+                status: U256::from(receipt.receipt.success),
+                cumulative_gas_used: U256::from(receipt.receipt.cumulative_gas_used),
+                logs,
+                logs_bloom: receipt.bloom,
+                transaction_hash: tx.hash(),
+                transaction_index: U256::from(index),
+                block_hash: block.hash(),
+                block_number: U256::from(block.number),
+                gas_used: U256::from(gas_used),
+                effective_gas_price: U256::from(tx.effective_gas_price(base_fee)),
+                blob_gas_used: tx.blob_gas_used().map(U256::from),
+                blob_gas_price: tx.blob_gas_used().and(blob_gas_price),
+                from,
+                to,
+                contract_address,
+            }
+        })
+        .collect()
+}
+
+/// Writes the receipts of `block` as a JSON array to `path`
+pub fn export_block_receipts(
+    path: &Path,
+    block: &SealedBlock,
+    senders: &[Address],
+    receipts: &[ReceiptWithBloom],
+) -> Result<(), PbbError> {
+    let json = serde_json::to_string_pretty(&block_receipts_json(block, senders, receipts))?;
+    std::fs::write(path, json).map_err(|source| PbbError::Io {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+fn to_rpc_log(
+    log: &Log,
+    block: &SealedBlock,
+    transaction_hash: B256,
+    transaction_index: usize,
+    log_index: usize,
+) -> RpcLog {
+    RpcLog {
+        address: log.address,
+        topics: log.topics().to_vec(),
+        data: log.data.data.clone(),
+        block_hash: block.hash(),
+        block_number: U256::from(block.number),
+        transaction_hash,
+        transaction_index: U256::from(transaction_index),
+        log_index: U256::from(log_index),
+        removed: false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reth_primitives::Block;
+    use reth_primitives::Header;
+    use reth_primitives::Signature;
+    use reth_primitives::Transaction;
+    use reth_primitives::TxKind;
+    use reth_primitives::TxLegacy;
+    use serde_json::json;
+
+    use super::*;
+    use crate::test_utils::blob_tx;
+    use crate::test_utils::tx;
+    use crate::test_utils::TRANSFER_GAS;
+
+    const ALICE: Address = Address::repeat_byte(0xa1);
+    const BOB: Address = Address::repeat_byte(0xb0);
+    const CAROL: Address = Address::repeat_byte(0xc0);
+    const RECIPIENT: Address = Address::repeat_byte(0xee);
+
+    fn receipt(
+        tx: &TransactionSigned,
+        success: bool,
+        cumulative_gas_used: u64,
+        logs: Vec<Log>,
+    ) -> ReceiptWithBloom {
+        Receipt {
+            tx_type: tx.tx_type(),
+            success,
+            cumulative_gas_used,
+            logs,
+        }
+        .with_bloom()
+    }
+
+    fn log(topic: u8) -> Log {
+        Log::new_unchecked(
+            RECIPIENT,
+            vec![B256::repeat_byte(topic)],
+            Bytes::from_static(&[topic]),
+        )
+    }
+
+    #[test]
+    fn block_receipts_in_the_rpc_shape() {
+        // a transfer with two logs, a reverted contract creation and a blob transaction
+        // whose log continues the log index of the block
+        let transfer = tx(ALICE, 0, TRANSFER_GAS, 100, 1).into_signed();
+        let creation = TransactionSigned::from_transaction_and_signature(
+            Transaction::Legacy(TxLegacy {
+                nonce: 2,
+                gas_price: 10,
+                gas_limit: 100_000,
+                to: TxKind::Create,
+                ..Default::default()
+            }),
+            Signature::default(),
+        );
+        let blob = blob_tx(CAROL, 0, 1, 5).into_signed();
+        let block = Block {
+            header: Header {
+                number: 1,
+                base_fee_per_gas: Some(7),
+                excess_blob_gas: Some(0),
+                ..Default::default()
+            },
+            body: vec![transfer.clone(), creation.clone(), blob.clone()],
+            ..Default::default()
+        }
+        .seal_slow();
+        let receipts = vec![
+            receipt(&transfer, true, 21_000, vec![log(1), log(2)]),
+            receipt(&creation, false, 74_000, Vec::new()),
+            receipt(&blob, true, 95_000, vec![log(3)]),
+        ];
+
+        let rpc_log =
+            |topic: u8, tx: &TransactionSigned, transaction_index: &str, log_index: &str| {
+                json!({
+                    "address": RECIPIENT,
+                    "topics": [B256::repeat_byte(topic)],
+                    "data": Bytes::from(vec![topic]),
+                    "blockHash": block.hash(),
+                    "blockNumber": "0x1",
+                    "transactionHash": tx.hash(),
+                    "transactionIndex": transaction_index,
+                    "logIndex": log_index,
+                    "removed": false,
+                })
+            };
+        let json =
+            serde_json::to_value(block_receipts_json(&block, &[ALICE, BOB, CAROL], &receipts))
+                .unwrap();
+        assert_eq!(
+            json,
+            json!([
+                {
+                    "type": "0x2",
+                    "status": "0x1",
+                    "cumulativeGasUsed": "0x5208",
+                    "logs": [rpc_log(1, &transfer, "0x0", "0x0"), rpc_log(2, &transfer, "0x0", "0x1")],
+                    "logsBloom": receipts[0].bloom,
+                    "transactionHash": transfer.hash(),
+                    "transactionIndex": "0x0",
+                    "blockHash": block.hash(),
+                    "blockNumber": "0x1",
+                    "gasUsed": "0x5208",
+                    "effectiveGasPrice": "0x8",
+                    "from": ALICE,
+                    "to": RECIPIENT,
+                    "contractAddress": null,
+                },
+                {
+                    "type": "0x0",
+                    "status": "0x0",
+                    "cumulativeGasUsed": "0x12110",
+                    "logs": [],
+                    "logsBloom": Bloom::ZERO,
+                    "transactionHash": creation.hash(),
+                    "transactionIndex": "0x1",
+                    "blockHash": block.hash(),
+                    "blockNumber": "0x1",
+                    "gasUsed": "0xcf08",
+                    "effectiveGasPrice": "0xa",
+                    "from": BOB,
+                    "to": null,
+                    "contractAddress": BOB.create(2),
+                },
+                {
+                    "type": "0x3",
+                    "status": "0x1",
+                    "cumulativeGasUsed": "0x17318",
+                    "logs": [rpc_log(3, &blob, "0x2", "0x2")],
+                    "logsBloom": receipts[2].bloom,
+                    "transactionHash": blob.hash(),
+                    "transactionIndex": "0x2",
+                    "blockHash": block.hash(),
+                    "blockNumber": "0x1",
+                    "gasUsed": "0x5208",
+                    "effectiveGasPrice": "0x8",
+                    "blobGasUsed": "0x20000",
+                    "blobGasPrice": "0x1",
+                    "from": CAROL,
+                    "to": RECIPIENT,
+                    "contractAddress": null,
+                },
+            ])
+        );
+    }
+}
@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+
+use log::warn;
+use rayon::prelude::*;
+use reth_primitives::Address;
+use reth_primitives::TransactionSigned;
+use reth_primitives::TransactionSignedEcRecovered;
+use reth_primitives::TxHash;
+
+use crate::error::PbbError;
+
+/// Signers of the transactions seen so far by transaction hash, so that a transaction
+/// that stays in the mempool over the rebuilds of a slot is only recovered once
+#[derive(Debug, Default)]
+pub struct SenderCache {
+    senders: HashMap<TxHash, Address>,
+}
+
+impl SenderCache {
+    /// Recovers the signers of `txs` in parallel, reusing the cached ones.
+    ///
+    /// Transactions whose signer can't be recovered are dropped.
+    pub fn recover(&mut self, txs: Vec<TransactionSigned>) -> Vec<TransactionSignedEcRecovered> {
+        let senders = &self.senders;
+        let recovered: Vec<(TransactionSigned, Option<Address>)> = txs
+            .into_par_iter()
+            .map(|tx| {
+                let sender = senders
+                    .get(&tx.hash())
+                    .copied()
+                    .or_else(|| tx.recover_signer());
+                (tx, sender)
+            })
+            .collect();
+
+        recovered
+            .into_iter()
+            .filter_map(|(tx, sender)| {
+                let Some(sender) = sender else {
+                    warn!("Dropping tx {} with an invalid signature", tx.hash());
+                    return None;
+                };
+                self.senders.insert(tx.hash(), sender);
+                Some(TransactionSignedEcRecovered::from_signed_transaction(
+                    tx, sender,
+                ))
+            })
+            .collect()
+    }
+}
+
+/// Recovers the signers of `txs` in parallel, dropping the transactions with an
+/// invalid signature
+pub fn recover_signers(txs: Vec<TransactionSigned>) -> Vec<TransactionSignedEcRecovered> {
+    SenderCache::default().recover(txs)
+}
+
+/// Recovers the signers of `txs` in parallel, failing on the first transaction with an
+/// invalid signature, like the transactions of an existing block
+pub fn recover_all_signers(
+    txs: Vec<TransactionSigned>,
+) -> Result<Vec<TransactionSignedEcRecovered>, PbbError> {
+    txs.into_par_iter()
+        .map(|tx| {
+            let tx_hash = tx.hash();
+            tx.into_ecrecovered()
+                .ok_or(PbbError::SignatureRecovery { tx_hash })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use reth_primitives::Signature;
+    use reth_primitives::B256;
+
+    use super::*;
+    use crate::test_utils::signed_tx;
+    use crate::test_utils::tx;
+    use crate::test_utils::TRANSFER_GAS;
+
+    const SECRET_KEY: B256 = B256::repeat_byte(0x01);
+    const CAROL: Address = Address::repeat_byte(0xc0);
+
+    fn signers(txs: &[TransactionSignedEcRecovered]) -> Vec<Address> {
+        txs.iter().map(|tx| tx.signer()).collect()
+    }
+
+    #[test]
+    fn caches_the_recovered_signers() {
+        let signed = signed_tx(SECRET_KEY, 0);
+        let signer = signed.recover_signer().unwrap();
+        let mut cache = SenderCache::default();
+
+        assert_eq!(signers(&cache.recover(vec![signed.clone()])), vec![signer]);
+        assert_eq!(cache.senders.get(&signed.hash()), Some(&signer));
+    }
+
+    #[test]
+    fn reuses_the_cached_signers() {
+        let signed = signed_tx(SECRET_KEY, 0);
+        let mut cache = SenderCache::default();
+        // a cached signer is trusted without recovering the signature again
+        cache.senders.insert(signed.hash(), CAROL);
+
+        let recovered = cache.recover(vec![signed, signed_tx(SECRET_KEY, 1)]);
+        assert_eq!(recovered.len(), 2);
+        assert_eq!(recovered[0].signer(), CAROL);
+        assert_ne!(recovered[1].signer(), CAROL);
+        assert_eq!(cache.senders.len(), 2);
+    }
+
+    #[test]
+    fn drops_invalid_signatures_without_caching_them() {
+        let unsigned = tx(CAROL, 0, TRANSFER_GAS, 100, 1).into_signed();
+        let mut cache = SenderCache::default();
+
+        let recovered = cache.recover(vec![unsigned, signed_tx(SECRET_KEY, 0)]);
+        assert_eq!(recovered.len(), 1);
+        assert_ne!(recovered[0].signer(), CAROL);
+        assert_eq!(cache.senders.len(), 1);
+    }
+
+    #[test]
+    fn does_not_recover_cached_transactions_again() {
+        let signed = signed_tx(SECRET_KEY, 0);
+        let mut cache = SenderCache::default();
+        let signer = signers(&cache.recover(vec![signed.clone()]));
+
+        // same hash with a signature nothing can be recovered from
+        let mut tampered = signed;
+        tampered.signature = Signature::default();
+        assert_eq!(tampered.recover_signer(), None);
+        assert_eq!(signers(&cache.recover(vec![tampered])), signer);
+    }
+}
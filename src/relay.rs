@@ -0,0 +1,344 @@
+use std::fmt;
+use std::str::FromStr;
+
+use blst::min_pk::PublicKey;
+use blst::min_pk::SecretKey;
+use blst::min_pk::Signature;
+use blst::BLST_ERROR;
+use log::info;
+use reth_primitives::hex;
+use reth_primitives::Address;
+use reth_primitives::B256;
+use reth_rpc_types::beacon::relay::BidTrace;
+use reth_rpc_types::beacon::relay::SignedBidSubmissionV3;
+use reth_rpc_types::beacon::BlsPublicKey;
+use reth_rpc_types::beacon::BlsSignature;
+use reth_rpc_types_compat::engine::payload::block_to_payload_v3;
+use sha2::Digest;
+use sha2::Sha256;
+use ssz::Encode;
+
+use crate::builder::SlotPayload;
+use crate::error::PbbError;
+
+/// Path of the relay endpoint builders submit blocks to
+pub const SUBMIT_BLOCK_PATH: &str = "/relay/v1/builder/blocks";
+
+/// Domain type of builder API messages
+const DOMAIN_APPLICATION_BUILDER: [u8; 4] = [0, 0, 0, 1];
+
+/// Ciphersuite of the BLS signatures of the consensus layer
+const BLS_DST: &[u8] = b"BLS_SIG_BLS12381G2_XMD:SHA-256_SSWU_RO_POP_";
+
+/// Wire encoding of a block submission
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SubmissionEncoding {
+    #[default]
+    Json,
+    Ssz,
+}
+
+impl FromStr for SubmissionEncoding {
+    type Err = PbbError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Self::Json),
+            "ssz" => Ok(Self::Ssz),
+            other => Err(PbbError::UnknownSubmissionEncoding(other.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for SubmissionEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Json => write!(f, "json"),
+            Self::Ssz => write!(f, "ssz"),
+        }
+    }
+}
+
+/// BLS key the builder signs its bids with
+#[derive(Clone)]
+pub struct BuilderSigner {
+    secret_key: SecretKey,
+    domain: B256,
+}
+
+impl BuilderSigner {
+    /// Creates a signer from a hex encoded BLS secret key for the network with
+    /// `genesis_fork_version`
+    pub fn from_hex(secret_key: &str, genesis_fork_version: [u8; 4]) -> Result<Self, PbbError> {
+        let bytes = hex::decode(secret_key)?;
+        let secret_key = SecretKey::from_bytes(&bytes).map_err(PbbError::InvalidBlsKey)?;
+        Ok(Self {
+            secret_key,
+            domain: builder_domain(genesis_fork_version),
+        })
+    }
+
+    /// Returns the public key relays know the builder by
+    pub fn public_key(&self) -> BlsPublicKey {
+        BlsPublicKey::from(self.secret_key.sk_to_pk().to_bytes())
+    }
+
+    /// Signs a bid trace over the builder domain
+    pub fn sign(&self, message: &BidTrace) -> BlsSignature {
+        let signing_root = signing_root(message, self.domain);
+        let signature = self.secret_key.sign(signing_root.as_slice(), BLS_DST, &[]);
+        BlsSignature::from(signature.to_bytes())
+    }
+}
+
+impl fmt::Debug for BuilderSigner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BuilderSigner")
+            .field("public_key", &self.public_key())
+            .finish()
+    }
+}
+
+/// Checks the signature of a bid trace against the builder key it names
+pub fn verify_bid_signature(
+    message: &BidTrace,
+    signature: &BlsSignature,
+    genesis_fork_version: [u8; 4],
+) -> Result<(), PbbError> {
+    let public_key = PublicKey::from_bytes(message.builder_pubkey.as_slice())
+        .map_err(PbbError::InvalidBlsKey)?;
+    let signature =
+        Signature::from_bytes(signature.as_slice()).map_err(PbbError::InvalidBidSignature)?;
+    let signing_root = signing_root(message, builder_domain(genesis_fork_version));
+    match signature.verify(
+        true,
+        signing_root.as_slice(),
+        BLS_DST,
+        &[],
+        &public_key,
+        true,
+    ) {
+        BLST_ERROR::BLST_SUCCESS => Ok(()),
+        e => Err(PbbError::InvalidBidSignature(e)),
+    }
+}
+
+/// Client submitting the built blocks of a slot to a set of relays
+#[derive(Debug, Clone)]
+pub struct RelayClient {
+    client: reqwest::Client,
+    relay_urls: Vec<String>,
+    signer: BuilderSigner,
+    encoding: SubmissionEncoding,
+}
+
+/// Proposer of the slot a block is built for
+#[derive(Debug, Clone, Copy)]
+pub struct Proposer {
+    pub pubkey: BlsPublicKey,
+    pub fee_recipient: Address,
+}
+
+impl RelayClient {
+    /// Creates a client submitting to every relay of `relay_urls`
+    pub fn new(
+        relay_urls: Vec<String>,
+        signer: BuilderSigner,
+        encoding: SubmissionEncoding,
+    ) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            relay_urls,
+            signer,
+            encoding,
+        }
+    }
+
+    /// Signs a submission of the payload for `proposer`
+    pub fn signed_submission(
+        &self,
+        payload: &SlotPayload,
+        proposer: &Proposer,
+    ) -> SignedBidSubmissionV3 {
+        let block = &payload.built.block;
+        let message = BidTrace {
+            slot: payload.slot,
+            parent_hash: block.parent_hash,
+            block_hash: block.hash(),
+            builder_pubkey: self.signer.public_key(),
+            proposer_pubkey: proposer.pubkey,
+            proposer_fee_recipient: proposer.fee_recipient,
+            gas_limit: block.gas_limit,
+            gas_used: block.gas_used,
+            value: payload.value,
+        };
+        let signature = self.signer.sign(&message);
+        SignedBidSubmissionV3 {
+            message,
+            execution_payload: block_to_payload_v3(block.clone()),
+            blobs_bundle: payload.blobs_bundle.clone(),
+            signature,
+        }
+    }
+
+    /// Submits the payload to every relay, returning the first error if any relay rejected it
+    pub async fn submit(&self, payload: &SlotPayload, proposer: &Proposer) -> Result<(), PbbError> {
+        let submission = self.signed_submission(payload, proposer);
+        let (content_type, body) = match self.encoding {
+            SubmissionEncoding::Json => ("application/json", serde_json::to_vec(&submission)?),
+            SubmissionEncoding::Ssz => ("application/octet-stream", submission.as_ssz_bytes()),
+        };
+
+        let mut result = Ok(());
+        for relay_url in &self.relay_urls {
+            let url = format!("{}{}", relay_url.trim_end_matches('/'), SUBMIT_BLOCK_PATH);
+            let response = self
+                .client
+                .post(&url)
+                .header("Content-Type", content_type)
+                .body(body.clone())
+                .send()
+                .await;
+            match response {
+                Ok(response) if response.status().is_success() => info!(
+                    "Submitted block {} for slot {} to {}",
+                    submission.message.block_hash, submission.message.slot, relay_url
+                ),
+                Ok(response) => {
+                    let status = response.status();
+                    let reason = response.text().await.unwrap_or_default();
+                    if result.is_ok() {
+                        result = Err(PbbError::RelayRejected {
+                            relay_url: relay_url.clone(),
+                            status,
+                            reason,
+                        });
+                    }
+                }
+                Err(source) => {
+                    if result.is_ok() {
+                        result = Err(PbbError::RelayRequest {
+                            relay_url: relay_url.clone(),
+                            source,
+                        });
+                    }
+                }
+            }
+        }
+        result
+    }
+}
+
+/// Computes the builder signing domain of the network with `genesis_fork_version`.
+///
+/// Builder messages are signed with an empty genesis validators root.
+fn builder_domain(genesis_fork_version: [u8; 4]) -> B256 {
+    let mut version = [0u8; 32];
+    version[..4].copy_from_slice(&genesis_fork_version);
+    let fork_data_root = hash_pair(&version, &[0u8; 32]);
+
+    let mut domain = [0u8; 32];
+    domain[..4].copy_from_slice(&DOMAIN_APPLICATION_BUILDER);
+    domain[4..].copy_from_slice(&fork_data_root[..28]);
+    B256::from(domain)
+}
+
+/// Returns the root signed over for `message`, the hash tree root of its signing data
+fn signing_root(message: &BidTrace, domain: B256) -> B256 {
+    B256::from(hash_pair(&bid_trace_root(message), &domain.0))
+}
+
+/// SSZ hash tree root of a bid trace
+fn bid_trace_root(message: &BidTrace) -> [u8; 32] {
+    let mut leaves = [[0u8; 32]; 16];
+    leaves[0] = u64_leaf(message.slot);
+    leaves[1] = message.parent_hash.0;
+    leaves[2] = message.block_hash.0;
+    leaves[3] = pubkey_root(&message.builder_pubkey);
+    leaves[4] = pubkey_root(&message.proposer_pubkey);
+    leaves[5][..20].copy_from_slice(message.proposer_fee_recipient.as_slice());
+    leaves[6] = u64_leaf(message.gas_limit);
+    leaves[7] = u64_leaf(message.gas_used);
+    leaves[8] = message.value.to_le_bytes::<32>();
+    merkleize(&leaves)
+}
+
+fn u64_leaf(value: u64) -> [u8; 32] {
+    let mut leaf = [0u8; 32];
+    leaf[..8].copy_from_slice(&value.to_le_bytes());
+    leaf
+}
+
+/// A 48 byte key spans two chunks
+fn pubkey_root(pubkey: &BlsPublicKey) -> [u8; 32] {
+    let mut chunks = [0u8; 64];
+    chunks[..48].copy_from_slice(pubkey.as_slice());
+    hash_pair(
+        chunks[..32].try_into().unwrap(),
+        chunks[32..].try_into().unwrap(),
+    )
+}
+
+/// Merkleizes a power of two number of chunks
+fn merkleize(leaves: &[[u8; 32]]) -> [u8; 32] {
+    let mut layer = leaves.to_vec();
+    while layer.len() > 1 {
+        layer = layer
+            .chunks(2)
+            .map(|pair| hash_pair(&pair[0], &pair[1]))
+            .collect();
+    }
+    layer[0]
+}
+
+fn hash_pair(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use reth_primitives::b256;
+    use reth_primitives::U256;
+
+    use super::*;
+
+    /// Builder domain of mainnet, as used by mev-boost and the relays
+    const MAINNET_BUILDER_DOMAIN: B256 =
+        b256!("00000001f5a5fd42d16a20302798ef6ed309979b43003d2320d9f0e8ea9831a9");
+
+    fn bid_trace() -> BidTrace {
+        BidTrace {
+            slot: 9_000_000,
+            parent_hash: B256::repeat_byte(0x11),
+            block_hash: B256::repeat_byte(0x22),
+            builder_pubkey: BlsPublicKey::repeat_byte(0x33),
+            proposer_pubkey: BlsPublicKey::repeat_byte(0x44),
+            proposer_fee_recipient: Address::repeat_byte(0x55),
+            gas_limit: 30_000_000,
+            gas_used: 15_000_000,
+            value: U256::from(1_000_000_000_000_000_000u128),
+        }
+    }
+
+    #[test]
+    fn builder_domain_of_mainnet() {
+        assert_eq!(builder_domain([0, 0, 0, 0]), MAINNET_BUILDER_DOMAIN);
+    }
+
+    #[test]
+    fn bid_trace_signing_root() {
+        // computed with the tree_hash crate of Lighthouse
+        let trace = bid_trace();
+        assert_eq!(
+            B256::from(bid_trace_root(&trace)),
+            b256!("2626ebef98a0e442ab81bc66a58d537d9cd790d9b4e1d6c5f39947e9c1ef4d94")
+        );
+        assert_eq!(
+            signing_root(&trace, MAINNET_BUILDER_DOMAIN),
+            b256!("a30f4b138945767d1e9002344971eaff050a1ce4a64d345ebc0c18d1d1b367e3")
+        );
+    }
+}
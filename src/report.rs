@@ -0,0 +1,346 @@
+use std::path::Path;
+
+use reth_primitives::Account;
+use reth_primitives::Address;
+use reth_primitives::TransactionSigned;
+use reth_primitives::TxHash;
+use reth_primitives::B256;
+use reth_primitives::U256;
+use serde::Serialize;
+
+use crate::bundle::Bundle;
+use crate::error::PbbError;
+use crate::pbb::PevmExecution;
+
+/// What executing some transactions is worth, and what it burns
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockValue {
+    pub gas_used: u64,
+    /// Increase of the coinbase balance, priority fees and direct transfers included
+    pub coinbase_delta: U256,
+    /// Priority fees paid to the coinbase
+    pub priority_fees: U256,
+    /// Base fee burned by the transactions
+    pub burned_base_fee: U256,
+    /// Blob fees burned by the blob transactions
+    pub blob_fees: U256,
+}
+
+impl BlockValue {
+    fn add(&mut self, other: &BlockValue) {
+        self.gas_used += other.gas_used;
+        self.coinbase_delta += other.coinbase_delta;
+        self.priority_fees += other.priority_fees;
+        self.burned_base_fee += other.burned_base_fee;
+        self.blob_fees += other.blob_fees;
+    }
+}
+
+/// Value of a transaction of the block
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct TxValue {
+    pub hash: TxHash,
+    pub success: bool,
+    /// Bundle the transaction was included with
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bundle: Option<B256>,
+    #[serde(flatten)]
+    pub value: BlockValue,
+}
+
+/// Value of a bundle of the block, the sum of the values of its transactions
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleValue {
+    pub hash: B256,
+    #[serde(flatten)]
+    pub value: BlockValue,
+}
+
+/// Value of a built block to the proposer, broken down per transaction and per bundle
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildReport {
+    pub block_number: u64,
+    pub coinbase: Address,
+    /// Value transferred to the proposer by the builder payment, in builder-key mode
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proposer_payment: Option<U256>,
+    /// Totals over the whole block
+    #[serde(flatten)]
+    pub total: BlockValue,
+    pub txs: Vec<TxValue>,
+    pub bundles: Vec<BundleValue>,
+}
+
+impl BuildReport {
+    /// Computes the value of the transactions of `execution`, in the same order as
+    /// `txs`, for a coinbase holding `coinbase_before` before the block
+    pub fn from_execution(
+        txs: &[TransactionSigned],
+        bundles: &[Bundle],
+        execution: &PevmExecution,
+        coinbase_before: Option<Account>,
+        proposer_payment: Option<U256>,
+    ) -> Self {
+        let block_env = &execution.block_env;
+        let coinbase = block_env.coinbase;
+        let base_fee = block_env.basefee.saturating_to::<u64>();
+        let blob_gas_price = block_env
+            .blob_excess_gas_and_price
+            .as_ref()
+            .map(|blob| blob.blob_gasprice)
+            .unwrap_or_default();
+
+        let mut coinbase_balance = coinbase_before.map(|a| a.balance).unwrap_or_default();
+        let mut previous_cumulative_gas_used = 0;
+        let tx_values: Vec<TxValue> = txs
+            .iter()
+            .zip(&execution.results)
+            .map(|(tx, result)| {
+                let cumulative_gas_used = result.receipt.cumulative_gas_used as u64;
+                let gas_used = cumulative_gas_used - previous_cumulative_gas_used;
+                previous_cumulative_gas_used = cumulative_gas_used;
+
+                // the coinbase only shows up in the state of the transactions touching it
+                let balance_before = coinbase_balance;
+                if let Some(account) = result.state.get(&coinbase) {
+                    coinbase_balance = account
+                        .as_ref()
+                        .map(|account| account.basic.balance)
+                        .unwrap_or_default();
+                }
+
+                let tip = tx.effective_tip_per_gas(Some(base_fee)).unwrap_or_default();
+                let blob_gas_used = tx.blob_gas_used().unwrap_or_default();
+                TxValue {
+                    hash: tx.hash(),
+                    success: result.receipt.status.coerce_status(),
+                    bundle: bundles
+                        .iter()
+                        .find(|bundle| bundle.contains(&tx.hash()))
+                        .map(Bundle::hash),
+                    value: BlockValue {
+                        gas_used,
+                        coinbase_delta: coinbase_balance.saturating_sub(balance_before),
+                        priority_fees: U256::from(gas_used) * U256::from(tip),
+                        burned_base_fee: U256::from(gas_used) * U256::from(base_fee),
+                        blob_fees: U256::from(blob_gas_used) * U256::from(blob_gas_price),
+                    },
+                }
+            })
+            .collect();
+
+        let bundle_values = bundles
+            .iter()
+            .map(|bundle| {
+                let mut value = BlockValue::default();
+                for tx in tx_values
+                    .iter()
+                    .filter(|tx| tx.bundle == Some(bundle.hash()))
+                {
+                    value.add(&tx.value);
+                }
+                BundleValue {
+                    hash: bundle.hash(),
+                    value,
+                }
+            })
+            .collect();
+
+        let mut total = BlockValue::default();
+        for tx in &tx_values {
+            total.add(&tx.value);
+        }
+        // the builder payment takes coinbase balance back, the block delta accounts for it
+        let coinbase_initial = coinbase_before.map(|a| a.balance).unwrap_or_default();
+        total.coinbase_delta = coinbase_balance.saturating_sub(coinbase_initial);
+
+        Self {
+            block_number: block_env.number.saturating_to(),
+            coinbase,
+            proposer_payment,
+            total,
+            txs: tx_values,
+            bundles: bundle_values,
+        }
+    }
+
+    /// Returns what the block is worth to the proposer: the builder payment in
+    /// builder-key mode, the coinbase balance delta otherwise
+    pub fn proposer_value(&self) -> U256 {
+        self.proposer_payment.unwrap_or(self.total.coinbase_delta)
+    }
+}
+
+/// Writes `report` as JSON to `path`
+pub fn export_build_report(path: &Path, report: &BuildReport) -> Result<(), PbbError> {
+    let json = serde_json::to_string_pretty(report)?;
+    std::fs::write(path, json).map_err(|source| PbbError::Io {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::time::Duration;
+
+    use pevm::AccountBasic;
+    use pevm::EvmAccount;
+    use pevm::PevmTxExecutionResult;
+    use reth_primitives::TransactionSignedEcRecovered;
+    use reth_rpc_types::Receipt;
+    use reth_trie::HashedPostState;
+    use serde_json::json;
+
+    use super::*;
+    use crate::test_utils::tx;
+    use crate::test_utils::TRANSFER_GAS;
+
+    const ALICE: Address = Address::repeat_byte(0xa1);
+    const BOB: Address = Address::repeat_byte(0xb0);
+    const CAROL: Address = Address::repeat_byte(0xc0);
+    const COINBASE: Address = Address::repeat_byte(0xcb);
+    const BASE_FEE: u64 = 7;
+
+    /// Result of a transaction, leaving the coinbase with `coinbase_balance` if it
+    /// touched it
+    fn result(
+        success: bool,
+        cumulative_gas_used: u128,
+        coinbase_balance: Option<u64>,
+    ) -> PevmTxExecutionResult {
+        PevmTxExecutionResult {
+            receipt: Receipt {
+                status: success.into(),
+                cumulative_gas_used,
+                logs: Vec::new(),
+            },
+            state: coinbase_balance
+                .map(|balance| {
+                    let account = EvmAccount {
+                        basic: AccountBasic {
+                            balance: U256::from(balance),
+                            nonce: 0,
+                            code_hash: None,
+                            code: None,
+                        },
+                        storage: Default::default(),
+                    };
+                    (COINBASE, Some(account))
+                })
+                .into_iter()
+                .collect(),
+        }
+    }
+
+    /// Executes a bundled transaction paying the coinbase 5000 on top of its tip, a
+    /// reverted one and one without tip, the bundle's second transaction and a whole
+    /// other bundle having been dropped
+    fn report(proposer_payment: Option<U256>) -> (BuildReport, Vec<Bundle>) {
+        let bundled = tx(ALICE, 0, TRANSFER_GAS, 100, 1);
+        let dropped = tx(ALICE, 1, TRANSFER_GAS, 100, 1);
+        let reverted = tx(BOB, 0, 50_000, 100, 2);
+        let untipped = tx(CAROL, 0, TRANSFER_GAS, 100, 0);
+        let bundles = vec![
+            Bundle::new(vec![bundled.clone(), dropped], 1, HashSet::new()),
+            Bundle::new(vec![tx(CAROL, 1, TRANSFER_GAS, 100, 1)], 1, HashSet::new()),
+        ];
+        let execution = PevmExecution {
+            block_env: pevm::BlockEnv {
+                number: U256::from(1),
+                coinbase: COINBASE,
+                basefee: U256::from(BASE_FEE),
+                ..Default::default()
+            },
+            pre_block_changes: HashedPostState::default(),
+            results: vec![
+                result(true, 21_000, Some(1_000 + 21_000 + 5_000)),
+                result(false, 51_000, Some(27_000 + 60_000)),
+                result(true, 72_000, None),
+            ],
+            elapsed: Duration::ZERO,
+        };
+        let txs = [bundled, reverted, untipped].map(TransactionSignedEcRecovered::into_signed);
+        let coinbase_before = Account {
+            balance: U256::from(1_000),
+            ..Default::default()
+        };
+        let report = BuildReport::from_execution(
+            &txs,
+            &bundles,
+            &execution,
+            Some(coinbase_before),
+            proposer_payment,
+        );
+        (report, bundles)
+    }
+
+    fn value(gas_used: u64, coinbase_delta: u64, priority_fees: u64) -> BlockValue {
+        BlockValue {
+            gas_used,
+            coinbase_delta: U256::from(coinbase_delta),
+            priority_fees: U256::from(priority_fees),
+            burned_base_fee: U256::from(gas_used * BASE_FEE),
+            blob_fees: U256::ZERO,
+        }
+    }
+
+    #[test]
+    fn values_the_executed_transactions() {
+        let (report, _) = report(None);
+        let values: Vec<(bool, BlockValue)> = report
+            .txs
+            .iter()
+            .map(|tx| (tx.success, tx.value.clone()))
+            .collect();
+        assert_eq!(
+            values,
+            [
+                (true, value(21_000, 26_000, 21_000)),
+                (false, value(30_000, 60_000, 60_000)),
+                // the coinbase doesn't show up in the state of a transaction without tip
+                (true, value(21_000, 0, 0)),
+            ]
+        );
+        assert_eq!(report.block_number, 1);
+        assert_eq!(report.coinbase, COINBASE);
+        assert_eq!(report.total, value(72_000, 86_000, 81_000));
+    }
+
+    #[test]
+    fn bundles_only_count_their_included_transactions() {
+        let (report, bundles) = report(None);
+        assert_eq!(report.txs[0].bundle, Some(bundles[0].hash()));
+        assert!(report.txs[1..].iter().all(|tx| tx.bundle.is_none()));
+        assert_eq!(
+            report.bundles,
+            [
+                BundleValue {
+                    hash: bundles[0].hash(),
+                    value: value(21_000, 26_000, 21_000),
+                },
+                BundleValue {
+                    hash: bundles[1].hash(),
+                    value: BlockValue::default(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn proposer_value_is_the_payment_in_builder_key_mode() {
+        assert_eq!(report(None).0.proposer_value(), U256::from(86_000));
+
+        let (report, _) = report(Some(U256::from(50_000)));
+        assert_eq!(
+            serde_json::to_value(&report).unwrap()["proposerPayment"],
+            json!("0xc350")
+        );
+        assert_eq!(report.proposer_value(), U256::from(50_000));
+    }
+}
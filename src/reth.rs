@@ -1,80 +1,184 @@
 use log::info;
 use reth_evm::ConfigureEvm;
 use reth_node_ethereum::EthEvmConfig;
-use reth_primitives::{revm::config::revm_spec_by_timestamp_after_merge, TransactionSigned, U256};
-use reth_provider::{BlockReaderIdExt, StateProviderFactory};
+use reth_primitives::{
+    Address, ReceiptWithBloom, SealedHeader, TransactionSigned, TransactionSignedEcRecovered,
+    TxHash,
+};
+use reth_provider::{
+    BlockReaderIdExt, ChainSpecProvider, ProviderError, StateProviderBox, StateProviderFactory,
+};
 use reth_revm::{
     database::StateProviderDatabase,
     db::CacheDB,
-    interpreter::gas::ZERO,
     primitives::{
         BlobExcessGasAndPrice, BlockEnv, CfgEnv, CfgEnvWithHandlerCfg, EVMError, EnvWithHandlerCfg,
-        ExecutionResult, ResultAndState, SpecId,
+        EvmState, ExecutionResult, InvalidTransaction, ResultAndState,
     },
     DatabaseCommit,
 };
-use std::sync::Arc;
+use reth_rpc_types::engine::PayloadAttributes;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use crate::{
+    error::PbbError,
     lighthouse::BeaconEventsConfig,
-    reth_db::reth_db_provider,
-    utils::{chain_spec, get_tx_env_reth},
+    pbb::next_block_env,
+    receipts::receipt_from_reth,
+    recovery::recover_signers,
+    reth_db::RethProvider,
+    state_root::{
+        apply_withdrawals, compute_state_root, hashed_state_from_cache_db, log_state_root_timing,
+    },
+    system_calls::apply_pre_block_calls,
+    tx_env::tx_env,
+    utils::retain_chain_transactions,
 };
 
-pub async fn execute_reth(txs: Vec<TransactionSigned>) -> Vec<ExecutionResult> {
-    let provider = reth_db_provider();
+/// Outcome of executing transactions sequentially with the reth executor
+#[derive(Debug, Clone, Default)]
+pub struct RethExecutionOutcome {
+    /// Transactions that executed without an error, in execution order
+    pub transactions: Vec<TransactionSigned>,
+    pub results: Vec<ExecutionResult>,
+    pub receipts: Vec<ReceiptWithBloom>,
+    /// State changed by each executed transaction
+    pub states: Vec<EvmState>,
+    /// Transactions that failed to execute, with the reason
+    pub rejected: Vec<RejectedTx>,
+    /// Time spent executing the transactions
+    pub elapsed: Duration,
+}
+
+/// Transaction the EVM refused to execute, like one with a bad nonce, a balance too
+/// low to pay for its gas or a fee below the base fee
+#[derive(Debug, Clone)]
+pub struct RejectedTx {
+    pub hash: TxHash,
+    pub error: EVMError<ProviderError>,
+}
 
-    let beacon_client = BeaconEventsConfig::new();
-    let payload_attributes = beacon_client.run().await.unwrap().data.payload_attributes;
+impl RejectedTx {
+    /// Returns true if the transaction can't become valid on top of the same parent,
+    /// unlike one lacking funds or with a nonce gap that earlier transactions may fill
+    pub fn is_permanent(&self) -> bool {
+        matches!(
+            self.error,
+            EVMError::Transaction(
+                InvalidTransaction::NonceTooLow { .. }
+                    | InvalidTransaction::NonceOverflowInTransaction { .. }
+                    | InvalidTransaction::InvalidChainId { .. }
+                    | InvalidTransaction::PriorityFeeGreaterThanMaxFee { .. }
+                    | InvalidTransaction::CallGasCostMoreThanGasLimit { .. }
+                    | InvalidTransaction::CreateInitCodeSizeLimit { .. }
+                    | InvalidTransaction::EmptyBlobs { .. }
+                    | InvalidTransaction::TooManyBlobs { .. }
+                    | InvalidTransaction::BlobVersionNotSupported { .. }
+            )
+        )
+    }
+}
 
-    let chain_spec = chain_spec();
+/// Waits for the next payload attributes and executes `txs` on top of the latest block
+pub async fn execute_reth(
+    provider: &RethProvider,
+    beacon_client: BeaconEventsConfig,
+    txs: Vec<TransactionSigned>,
+) -> Result<RethExecutionOutcome, PbbError> {
+    let payload_attributes = beacon_client.run().await?.data.payload_attributes;
 
     let latest_block_header = provider
-        .latest_header()
-        .map_err(|_e| EVMError::Database(String::from("Error fetching latest sealed header")))
-        .unwrap()
-        .unwrap();
-
-    let latest_state = provider
-        .state_by_block_hash(latest_block_header.hash())
-        .map_err(|_| EVMError::Database(String::from("Error fetching latest state")))
-        .unwrap();
-    let state = Arc::new(StateProviderDatabase::new(latest_state));
+        .latest_header()?
+        .ok_or(PbbError::MissingLatestHeader)?;
+
+    let txs = retain_chain_transactions(recover_signers(txs), &provider.chain_spec());
+    execute_reth_with(provider, &latest_block_header, &payload_attributes, txs)
+}
+
+/// Executes `txs` one by one on top of the state of `parent`
+pub fn execute_reth_with(
+    provider: &RethProvider,
+    parent: &SealedHeader,
+    payload_attributes: &PayloadAttributes,
+    txs: Vec<TransactionSignedEcRecovered>,
+) -> Result<RethExecutionOutcome, PbbError> {
+    let (outcome, db) = execute_sequential(
+        provider,
+        parent,
+        payload_attributes,
+        payload_attributes.suggested_fee_recipient,
+        parent.gas_limit,
+        txs,
+    )?;
+
+    let mut hashed_state = hashed_state_from_cache_db(&db);
+    if let Some(withdrawals) = &payload_attributes.withdrawals {
+        let parent_state = provider.state_by_block_hash(parent.hash())?;
+        apply_withdrawals(&mut hashed_state, &parent_state, withdrawals)?;
+    }
+    match compute_state_root(provider, parent, &hashed_state) {
+        Ok(state_root) => log_state_root_timing(outcome.elapsed, &state_root),
+        Err(e) => info!("Error computing state root: {:?}", e),
+    }
+
+    Ok(outcome)
+}
+
+/// Returns the transactions of `txs` the EVM refuses to execute in order on top of `parent`,
+/// in a block of `gas_limit`
+pub fn find_invalid_transactions(
+    provider: &RethProvider,
+    parent: &SealedHeader,
+    payload_attributes: &PayloadAttributes,
+    coinbase: Address,
+    gas_limit: u64,
+    txs: Vec<TransactionSignedEcRecovered>,
+) -> Result<Vec<RejectedTx>, PbbError> {
+    let (outcome, _) = execute_sequential(
+        provider,
+        parent,
+        payload_attributes,
+        coinbase,
+        gas_limit,
+        txs,
+    )?;
+    Ok(outcome.rejected)
+}
+
+fn execute_sequential(
+    provider: &RethProvider,
+    parent: &SealedHeader,
+    payload_attributes: &PayloadAttributes,
+    coinbase: Address,
+    gas_limit: u64,
+    txs: Vec<TransactionSignedEcRecovered>,
+) -> Result<
+    (
+        RethExecutionOutcome,
+        CacheDB<Arc<StateProviderDatabase<StateProviderBox>>>,
+    ),
+    PbbError,
+> {
+    let chain_spec = provider.chain_spec();
+
+    let parent_state = provider.state_by_block_hash(parent.hash())?;
+    let state = Arc::new(StateProviderDatabase::new(parent_state));
     let mut db = CacheDB::new(Arc::clone(&state));
+    apply_pre_block_calls(&chain_spec, parent, payload_attributes, &mut db)?;
 
-    let spec_id = revm_spec_by_timestamp_after_merge(&chain_spec, payload_attributes.timestamp);
-
-    let base_fee = latest_block_header
-        .header()
-        .next_block_base_fee(chain_spec.base_fee_params_at_timestamp(payload_attributes.timestamp));
-
-    let blob_excess_gas_and_price = latest_block_header
-        .header()
-        .next_block_excess_blob_gas()
-        .or_else(|| {
-            if spec_id == SpecId::CANCUN {
-                // default excess blob gas is zero
-                Some(0)
-            } else {
-                None
-            }
-        })
-        .map(BlobExcessGasAndPrice::new);
-
-    let block_env = BlockEnv {
-        number: U256::from(latest_block_header.number + 1),
-        timestamp: U256::from(payload_attributes.timestamp),
-        coinbase: payload_attributes.suggested_fee_recipient,
-        gas_limit: U256::from(latest_block_header.gas_limit),
-        basefee: base_fee.map(U256::from).unwrap_or_default(),
-        difficulty: U256::from(ZERO),
-        prevrandao: Some(payload_attributes.prev_randao),
-        blob_excess_gas_and_price,
-    };
-
-    let mut execution_result = Vec::new();
+    let (block_env, spec_id) =
+        next_block_env(&chain_spec, parent, payload_attributes, coinbase, gas_limit);
+    let block_env = reth_block_env(block_env);
+
+    let mut outcome = RethExecutionOutcome::default();
+    let mut cumulative_gas_used = 0;
     info!("total txs: {:?}", txs.len());
 
+    let execution_start = Instant::now();
+
     for tx in txs {
         let cfg = CfgEnv::default().with_chain_id(chain_spec.chain().id());
         let cfgenvwithhandlercfg = CfgEnvWithHandlerCfg::new_with_spec_id(cfg, spec_id);
@@ -82,7 +186,7 @@ pub async fn execute_reth(txs: Vec<TransactionSigned>) -> Vec<ExecutionResult> {
         let env = EnvWithHandlerCfg::new_with_cfg_env(
             cfgenvwithhandlercfg,
             block_env.clone(),
-            get_tx_env_reth(tx),
+            tx_env(&tx),
         );
 
         let evm_config = EthEvmConfig::default();
@@ -94,12 +198,41 @@ pub async fn execute_reth(txs: Vec<TransactionSigned>) -> Vec<ExecutionResult> {
             Ok(result) => result,
             Err(e) => {
                 info!("Error executing transaction: {:?}", e);
+                outcome.rejected.push(RejectedTx {
+                    hash: tx.hash(),
+                    error: e,
+                });
                 continue;
             }
         };
         drop(evm);
+        outcome.states.push(state.clone());
         db.commit(state);
-        execution_result.push(result);
+
+        cumulative_gas_used += result.gas_used();
+        outcome
+            .receipts
+            .push(receipt_from_reth(&tx, &result, cumulative_gas_used));
+        outcome.results.push(result);
+        outcome.transactions.push(tx.into_signed());
+    }
+    outcome.elapsed = execution_start.elapsed();
+
+    Ok((outcome, db))
+}
+
+/// Converts the block environment of pevm into the one of the revm version of reth
+fn reth_block_env(block_env: pevm::BlockEnv) -> BlockEnv {
+    BlockEnv {
+        number: block_env.number,
+        coinbase: block_env.coinbase,
+        timestamp: block_env.timestamp,
+        gas_limit: block_env.gas_limit,
+        basefee: block_env.basefee,
+        difficulty: block_env.difficulty,
+        prevrandao: block_env.prevrandao,
+        blob_excess_gas_and_price: block_env
+            .blob_excess_gas_and_price
+            .map(|blob| BlobExcessGasAndPrice::new(blob.excess_blob_gas)),
     }
-    execution_result
 }
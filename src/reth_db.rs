@@ -1,8 +1,9 @@
 use std::path::Path;
+use std::path::PathBuf;
 use std::sync::Arc;
 
-use crate::utils::chain_spec;
 use reth_beacon_consensus::EthBeaconConsensus;
+use reth_chainspec::ChainSpec;
 use reth_db::open_db_read_only;
 use reth_db::DatabaseEnv;
 use reth_node_ethereum::EthExecutorProvider;
@@ -15,25 +16,63 @@ use reth_blockchain_tree::BlockchainTreeConfig;
 use reth_blockchain_tree::ShareableBlockchainTree;
 use reth_blockchain_tree::TreeExternals;
 
-pub fn reth_db_provider() -> Arc<BlockchainProvider<Arc<DatabaseEnv>>> {
-    let db_path = Path::new("/Users/chirag-bgh/Library/Application Support/reth/holesky/db");
-    let db = Arc::new(open_db_read_only(db_path, Default::default()).unwrap());
-    let chain_spec = chain_spec();
+use crate::error::PbbError;
 
-    let factory = ProviderFactory::new(
-        db.clone(),
-        chain_spec.clone(),
-        StaticFileProvider::read_only(db_path.join("static_files")).unwrap(),
+/// Provider over a reth database opened read only
+pub type RethProvider = BlockchainProvider<Arc<DatabaseEnv>>;
+
+/// Returns the datadir reth uses by default for `chain_spec` on this platform,
+/// e.g. `~/.local/share/reth/holesky` on Linux or
+/// `~/Library/Application Support/reth/holesky` on macOS
+pub fn default_datadir(chain_spec: &ChainSpec) -> Result<PathBuf, PbbError> {
+    let data_dir = dirs_next::data_dir().ok_or(PbbError::MissingDataDir)?;
+    Ok(data_dir.join("reth").join(chain_spec.chain.to_string()))
+}
+
+/// Opens the reth database of `datadir` read only.
+///
+/// Static files are read from `<datadir>/static_files` unless `static_files` is set.
+pub fn reth_db_provider(
+    chain_spec: Arc<ChainSpec>,
+    datadir: &Path,
+    static_files: Option<&Path>,
+) -> Result<Arc<RethProvider>, PbbError> {
+    let db_path = datadir.join("db");
+    if !db_path.join("mdbx.dat").exists() {
+        return Err(PbbError::MissingDatabase { path: db_path });
+    }
+    let db = Arc::new(
+        open_db_read_only(&db_path, Default::default()).map_err(|e| PbbError::OpenDatabase {
+            path: db_path.clone(),
+            source: e.into(),
+        })?,
     );
-    Arc::new({
-        let consensus = Arc::new(EthBeaconConsensus::new(chain_spec.clone()));
-        let executor = EthExecutorProvider::ethereum(chain_spec.clone());
 
-        let tree_externals = TreeExternals::new(factory.clone(), consensus, executor);
-        let tree =
-            BlockchainTree::new(tree_externals, BlockchainTreeConfig::default(), None).unwrap();
-        let blockchain_tree = Arc::new(ShareableBlockchainTree::new(tree));
+    let static_files_path = static_files
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| datadir.join("static_files"));
+    if !static_files_path.is_dir() {
+        return Err(PbbError::MissingStaticFiles {
+            path: static_files_path,
+        });
+    }
+    let static_file_provider =
+        StaticFileProvider::read_only(&static_files_path).map_err(|source| {
+            PbbError::OpenStaticFiles {
+                path: static_files_path.clone(),
+                source,
+            }
+        })?;
+
+    let factory = ProviderFactory::new(db.clone(), chain_spec.clone(), static_file_provider);
+    let consensus = Arc::new(EthBeaconConsensus::new(chain_spec.clone()));
+    let executor = EthExecutorProvider::ethereum(chain_spec.clone());
+
+    let tree_externals = TreeExternals::new(factory.clone(), consensus, executor);
+    let tree = BlockchainTree::new(tree_externals, BlockchainTreeConfig::default(), None)
+        .map_err(|e| PbbError::BlockchainTree(e.into()))?;
+    let blockchain_tree = Arc::new(ShareableBlockchainTree::new(tree));
 
-        BlockchainProvider::new(factory, blockchain_tree).unwrap()
-    })
+    let provider = BlockchainProvider::new(factory, blockchain_tree)?;
+    Ok(Arc::new(provider))
 }
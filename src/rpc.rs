@@ -0,0 +1,51 @@
+use reth_primitives::BlobTransactionSidecar;
+use reth_primitives::TransactionSigned;
+use reth_primitives::TxHash;
+use serde::{Deserialize, Serialize};
+
+use crate::error::PbbError;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RpcResponse<T> {
+    pub jsonrpc: String,
+    pub result: T,
+    pub id: u32,
+}
+
+/// Fetches the best transactions of the EL mempool, ordered by the pool
+pub async fn eth_get_best_transactions(
+    rpc_url: &str,
+) -> Result<RpcResponse<Vec<TransactionSigned>>, PbbError> {
+    let client = reqwest::Client::new();
+    let res = client
+        .post(rpc_url)
+        .header("Content-Type", "application/json")
+        .body(r#"{"jsonrpc":"2.0","method":"eth_getBestTransactions","params":[],"id":1}"#)
+        .send()
+        .await?;
+    let rpc_response = res.json::<RpcResponse<Vec<TransactionSigned>>>().await?;
+    Ok(rpc_response)
+}
+
+/// Fetches the blob sidecars of the given pooled blob transactions, `None` for the
+/// ones the EL pool has no sidecar for
+pub async fn eth_get_blob_sidecars(
+    rpc_url: &str,
+    tx_hashes: &[TxHash],
+) -> Result<RpcResponse<Vec<Option<BlobTransactionSidecar>>>, PbbError> {
+    let client = reqwest::Client::new();
+    let res = client
+        .post(rpc_url)
+        .json(&serde_json::json!({
+            "jsonrpc": "2.0",
+            "method": "eth_getBlobSidecars",
+            "params": [tx_hashes],
+            "id": 1,
+        }))
+        .send()
+        .await?;
+    let rpc_response = res
+        .json::<RpcResponse<Vec<Option<BlobTransactionSidecar>>>>()
+        .await?;
+    Ok(rpc_response)
+}
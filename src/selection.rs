@@ -0,0 +1,366 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use log::debug;
+use reth_primitives::constants::eip4844::DATA_GAS_PER_BLOB;
+use reth_primitives::constants::eip4844::MAX_DATA_GAS_PER_BLOCK;
+use reth_primitives::TransactionSignedEcRecovered;
+use reth_primitives::TxHash;
+use reth_primitives::U256;
+
+/// Limits of the block the transactions are selected for
+#[derive(Debug, Clone, Copy)]
+pub struct BlockLimits {
+    pub gas_limit: u64,
+    /// Gas kept free for transactions appended after the selection, like the builder payment
+    pub reserved_gas: u64,
+    pub max_blob_count: u64,
+    pub base_fee: u64,
+    /// Price of blob gas, `None` before Cancun
+    pub blob_gas_price: Option<u128>,
+}
+
+impl BlockLimits {
+    /// Returns the limits of a block executed in `block_env`, with `reserved_gas` and
+    /// `reserved_blobs` already taken by transactions outside of the selection
+    pub fn from_block_env(
+        block_env: &pevm::BlockEnv,
+        reserved_gas: u64,
+        reserved_blobs: u64,
+    ) -> Self {
+        Self {
+            gas_limit: block_env.gas_limit.saturating_to(),
+            reserved_gas,
+            max_blob_count: (MAX_DATA_GAS_PER_BLOCK / DATA_GAS_PER_BLOB)
+                .saturating_sub(reserved_blobs),
+            base_fee: block_env.basefee.saturating_to(),
+            blob_gas_price: block_env
+                .blob_excess_gas_and_price
+                .as_ref()
+                .map(|blob| blob.blob_gasprice),
+        }
+    }
+}
+
+/// Why a transaction was left out of the block
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExclusionReason {
+    /// The gas limit of the transaction exceeds the gas left in the block
+    GasLimit { tx_gas_limit: u64, remaining: u64 },
+    /// The blobs of the transaction exceed the blobs left in the block
+    BlobLimit { blobs: u64, remaining: u64 },
+    /// The max fee per gas is below the base fee of the block
+    FeeBelowBaseFee {
+        max_fee_per_gas: u128,
+        base_fee: u64,
+    },
+    /// The max fee per blob gas is below the blob gas price of the block
+    BlobFeeBelowBlobGasPrice {
+        max_fee_per_blob_gas: u128,
+        blob_gas_price: u128,
+    },
+    /// Blob transactions can't be included before Cancun
+    BlobsNotSupported,
+    /// The nonce is below the nonce of the sender account
+    StaleNonce { nonce: u64, account_nonce: u64 },
+    /// A bundle placed ahead of the transaction in the block uses the nonce
+    NonceTakenByBundle { nonce: u64, next_nonce: u64 },
+    /// Another transaction of the sender with the same nonce pays a higher tip
+    DuplicateNonce { nonce: u64 },
+    /// A nonce between the account nonce and this one has no transaction
+    NonceGap { nonce: u64, expected: u64 },
+    /// An earlier transaction of the sender was left out
+    PrecedingNonceExcluded,
+    /// The transaction reverted while paying the coinbase too little to be worth its gas
+    RevertedLowFee { fee: U256 },
+}
+
+impl ExclusionReason {
+    /// Returns true if the transaction could fit a later block as is, in which case
+    /// it is deferred rather than dropped
+    pub fn is_deferred(&self) -> bool {
+        // the base fee and the blob gas price may fall in later blocks
+        !matches!(
+            self,
+            Self::BlobsNotSupported | Self::StaleNonce { .. } | Self::DuplicateNonce { .. }
+        )
+    }
+}
+
+impl fmt::Display for ExclusionReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::GasLimit {
+                tx_gas_limit,
+                remaining,
+            } => write!(
+                f,
+                "gas limit {tx_gas_limit} exceeds the {remaining} gas left"
+            ),
+            Self::BlobLimit { blobs, remaining } => {
+                write!(f, "{blobs} blobs exceed the {remaining} blobs left")
+            }
+            Self::FeeBelowBaseFee {
+                max_fee_per_gas,
+                base_fee,
+            } => write!(f, "max fee {max_fee_per_gas} below base fee {base_fee}"),
+            Self::BlobFeeBelowBlobGasPrice {
+                max_fee_per_blob_gas,
+                blob_gas_price,
+            } => write!(
+                f,
+                "max blob fee {max_fee_per_blob_gas} below blob gas price {blob_gas_price}"
+            ),
+            Self::BlobsNotSupported => write!(f, "blob transactions are not supported yet"),
+            Self::StaleNonce {
+                nonce,
+                account_nonce,
+            } => write!(f, "nonce {nonce} below account nonce {account_nonce}"),
+            Self::NonceTakenByBundle { nonce, next_nonce } => write!(
+                f,
+                "nonce {nonce} taken by a bundle, next nonce {next_nonce}"
+            ),
+            Self::DuplicateNonce { nonce } => {
+                write!(f, "nonce {nonce} taken by a better paying transaction")
+            }
+            Self::NonceGap { nonce, expected } => {
+                write!(f, "nonce {nonce} leaves a gap after nonce {expected}")
+            }
+            Self::PrecedingNonceExcluded => {
+                write!(f, "an earlier transaction of the sender was left out")
+            }
+            Self::RevertedLowFee { fee } => write!(f, "reverted paying only {fee} to the coinbase"),
+        }
+    }
+}
+
+/// Transaction left out of a block
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExcludedTx {
+    pub hash: TxHash,
+    pub reason: ExclusionReason,
+}
+
+/// Transactions picked for a block, in their original order, and the ones left out
+#[derive(Debug, Clone, Default)]
+pub struct Selection {
+    pub selected: Vec<TransactionSignedEcRecovered>,
+    pub excluded: Vec<ExcludedTx>,
+}
+
+/// Picks the transactions of `txs` that fit in a block with `limits`, in order.
+/// Once a transaction is left out, the later transactions of its sender are too.
+///
+/// The gas limit of a transaction is taken as its gas usage since the actual usage
+/// is only known after execution, so the selection never overfills the block.
+pub fn select_transactions(
+    txs: Vec<TransactionSignedEcRecovered>,
+    limits: &BlockLimits,
+) -> Selection {
+    let mut selection = Selection::default();
+    let mut remaining_gas = limits.gas_limit.saturating_sub(limits.reserved_gas);
+    let mut remaining_blobs = limits.max_blob_count;
+    let mut blocked_senders = HashSet::new();
+
+    for tx in txs {
+        let blobs = tx
+            .blob_versioned_hashes()
+            .map_or(0, |hashes| hashes.len() as u64);
+
+        let sender = tx.signer();
+        let reason = if blocked_senders.contains(&sender) {
+            Some(ExclusionReason::PrecedingNonceExcluded)
+        } else if tx.max_fee_per_gas() < limits.base_fee as u128 {
+            Some(ExclusionReason::FeeBelowBaseFee {
+                max_fee_per_gas: tx.max_fee_per_gas(),
+                base_fee: limits.base_fee,
+            })
+        } else if tx.gas_limit() > remaining_gas {
+            Some(ExclusionReason::GasLimit {
+                tx_gas_limit: tx.gas_limit(),
+                remaining: remaining_gas,
+            })
+        } else if blobs > 0 {
+            match (limits.blob_gas_price, tx.max_fee_per_blob_gas()) {
+                (None, _) => Some(ExclusionReason::BlobsNotSupported),
+                (Some(_), _) if blobs > remaining_blobs => Some(ExclusionReason::BlobLimit {
+                    blobs,
+                    remaining: remaining_blobs,
+                }),
+                (Some(blob_gas_price), Some(max_fee_per_blob_gas))
+                    if max_fee_per_blob_gas < blob_gas_price =>
+                {
+                    Some(ExclusionReason::BlobFeeBelowBlobGasPrice {
+                        max_fee_per_blob_gas,
+                        blob_gas_price,
+                    })
+                }
+                _ => None,
+            }
+        } else {
+            None
+        };
+
+        match reason {
+            Some(reason) => {
+                debug!("Excluding tx {}: {}", tx.hash(), reason);
+                // the next transactions of the sender would have a nonce gap
+                blocked_senders.insert(sender);
+                selection.excluded.push(ExcludedTx {
+                    hash: tx.hash(),
+                    reason,
+                });
+            }
+            None => {
+                remaining_gas -= tx.gas_limit();
+                remaining_blobs -= blobs;
+                selection.selected.push(tx);
+            }
+        }
+    }
+    selection
+}
+
+#[cfg(test)]
+mod tests {
+    use reth_primitives::Address;
+
+    use super::*;
+    use crate::test_utils::blob_tx;
+    use crate::test_utils::tx;
+    use crate::test_utils::TRANSFER_GAS;
+
+    const ALICE: Address = Address::repeat_byte(0xa1);
+    const BOB: Address = Address::repeat_byte(0xb0);
+    const CAROL: Address = Address::repeat_byte(0xc0);
+
+    fn limits(gas_limit: u64) -> BlockLimits {
+        BlockLimits {
+            gas_limit,
+            reserved_gas: 0,
+            max_blob_count: 6,
+            base_fee: 10,
+            blob_gas_price: Some(1),
+        }
+    }
+
+    fn hashes(txs: &[TransactionSignedEcRecovered]) -> Vec<TxHash> {
+        txs.iter().map(|tx| tx.hash()).collect()
+    }
+
+    #[test]
+    fn keeps_the_order_of_the_transactions_that_fit() {
+        let txs = vec![
+            tx(ALICE, 0, TRANSFER_GAS, 20, 2),
+            tx(BOB, 0, TRANSFER_GAS, 20, 1),
+            tx(CAROL, 0, TRANSFER_GAS, 20, 3),
+        ];
+        let selection = select_transactions(txs.clone(), &limits(3 * TRANSFER_GAS));
+        assert_eq!(hashes(&selection.selected), hashes(&txs));
+        assert!(selection.excluded.is_empty());
+    }
+
+    #[test]
+    fn leaves_out_what_exceeds_the_gas_left() {
+        let txs = vec![
+            tx(ALICE, 0, TRANSFER_GAS, 20, 1),
+            tx(BOB, 0, 2 * TRANSFER_GAS, 20, 1),
+            tx(CAROL, 0, TRANSFER_GAS, 20, 1),
+        ];
+        let mut limits = limits(3 * TRANSFER_GAS);
+        limits.reserved_gas = TRANSFER_GAS;
+        let selection = select_transactions(txs.clone(), &limits);
+        assert_eq!(
+            hashes(&selection.selected),
+            vec![txs[0].hash(), txs[2].hash()]
+        );
+        assert_eq!(
+            selection.excluded,
+            vec![ExcludedTx {
+                hash: txs[1].hash(),
+                reason: ExclusionReason::GasLimit {
+                    tx_gas_limit: 2 * TRANSFER_GAS,
+                    remaining: TRANSFER_GAS,
+                },
+            }]
+        );
+    }
+
+    #[test]
+    fn blocks_the_sender_after_an_exclusion() {
+        let txs = vec![
+            tx(ALICE, 0, 2 * TRANSFER_GAS, 20, 1),
+            tx(ALICE, 1, TRANSFER_GAS, 20, 1),
+            tx(BOB, 0, TRANSFER_GAS, 20, 1),
+        ];
+        let selection = select_transactions(txs.clone(), &limits(TRANSFER_GAS));
+        assert_eq!(hashes(&selection.selected), vec![txs[2].hash()]);
+        assert_eq!(
+            selection.excluded[1],
+            ExcludedTx {
+                hash: txs[1].hash(),
+                reason: ExclusionReason::PrecedingNonceExcluded,
+            }
+        );
+    }
+
+    #[test]
+    fn leaves_out_fees_below_the_base_fee() {
+        let txs = vec![tx(ALICE, 0, TRANSFER_GAS, 9, 1)];
+        let selection = select_transactions(txs, &limits(TRANSFER_GAS));
+        assert!(selection.selected.is_empty());
+        assert_eq!(
+            selection.excluded[0].reason,
+            ExclusionReason::FeeBelowBaseFee {
+                max_fee_per_gas: 9,
+                base_fee: 10,
+            }
+        );
+        assert!(selection.excluded[0].reason.is_deferred());
+    }
+
+    #[test]
+    fn caps_the_blobs_of_the_block() {
+        let txs = vec![
+            blob_tx(ALICE, 0, 4, 1),
+            blob_tx(BOB, 0, 3, 1),
+            blob_tx(CAROL, 0, 2, 1),
+        ];
+        let selection = select_transactions(txs.clone(), &limits(3 * TRANSFER_GAS));
+        assert_eq!(
+            hashes(&selection.selected),
+            vec![txs[0].hash(), txs[2].hash()]
+        );
+        assert_eq!(
+            selection.excluded[0].reason,
+            ExclusionReason::BlobLimit {
+                blobs: 3,
+                remaining: 2,
+            }
+        );
+    }
+
+    #[test]
+    fn leaves_out_blobs_before_cancun_and_below_the_blob_gas_price() {
+        let txs = vec![blob_tx(ALICE, 0, 1, 1)];
+        let mut pre_cancun = limits(TRANSFER_GAS);
+        pre_cancun.blob_gas_price = None;
+        let selection = select_transactions(txs.clone(), &pre_cancun);
+        assert_eq!(
+            selection.excluded[0].reason,
+            ExclusionReason::BlobsNotSupported
+        );
+        assert!(!selection.excluded[0].reason.is_deferred());
+
+        let mut expensive_blobs = limits(TRANSFER_GAS);
+        expensive_blobs.blob_gas_price = Some(2);
+        let selection = select_transactions(txs, &expensive_blobs);
+        assert_eq!(
+            selection.excluded[0].reason,
+            ExclusionReason::BlobFeeBelowBlobGasPrice {
+                max_fee_per_blob_gas: 1,
+                blob_gas_price: 2,
+            }
+        );
+    }
+}
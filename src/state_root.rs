@@ -0,0 +1,259 @@
+use std::time::Duration;
+use std::time::Instant;
+
+use log::info;
+use pevm::PevmTxExecutionResult;
+use reth_primitives::keccak256;
+use reth_primitives::revm::compat::into_reth_acc;
+use reth_primitives::Account;
+use reth_primitives::SealedHeader;
+use reth_primitives::Withdrawal;
+use reth_primitives::B256;
+use reth_provider::AccountReader;
+use reth_provider::BlockHashReader;
+use reth_provider::DatabaseProviderFactory;
+use reth_provider::ProviderResult;
+use reth_revm::db::AccountState;
+use reth_revm::db::CacheDB;
+use reth_revm::primitives::KECCAK_EMPTY;
+use reth_revm::DatabaseRef;
+use reth_trie::HashedPostState;
+use reth_trie::HashedStorage;
+
+use crate::error::PbbError;
+use crate::reth_db::RethProvider;
+
+/// Computed state root together with the time spent computing it
+#[derive(Debug, Clone, Copy)]
+pub struct StateRootOutcome {
+    pub state_root: B256,
+    pub elapsed: Duration,
+}
+
+/// Builds the hashed post state from the per-transaction state transitions of pevm.
+///
+/// Transitions are applied in transaction order, so the last write to an account wins.
+pub fn hashed_state_from_pevm(results: &[PevmTxExecutionResult]) -> HashedPostState {
+    let mut hashed_state = HashedPostState::default();
+
+    for result in results {
+        for (address, account) in &result.state {
+            let hashed_address = keccak256(address);
+            match account {
+                Some(account) => {
+                    hashed_state.accounts.insert(
+                        hashed_address,
+                        Some(Account {
+                            nonce: account.basic.nonce,
+                            balance: account.basic.balance,
+                            bytecode_hash: account
+                                .basic
+                                .code_hash
+                                .filter(|hash| *hash != KECCAK_EMPTY),
+                        }),
+                    );
+                    let storage = hashed_state
+                        .storages
+                        .entry(hashed_address)
+                        .or_insert_with(|| HashedStorage::new(false));
+                    for (slot, value) in &account.storage {
+                        storage.storage.insert(keccak256(B256::from(*slot)), *value);
+                    }
+                }
+                None => {
+                    hashed_state.accounts.insert(hashed_address, None);
+                    hashed_state
+                        .storages
+                        .insert(hashed_address, HashedStorage::new(true));
+                }
+            }
+        }
+    }
+
+    hashed_state
+}
+
+/// Builds the hashed post state from the accounts committed into a [`CacheDB`].
+///
+/// Accounts that were only loaded and never touched by a transaction are skipped.
+pub fn hashed_state_from_cache_db<ExtDB: DatabaseRef>(db: &CacheDB<ExtDB>) -> HashedPostState {
+    let mut hashed_state = HashedPostState::default();
+
+    for (address, account) in &db.accounts {
+        let hashed_address = keccak256(address);
+        match account.account_state {
+            AccountState::None => continue,
+            AccountState::NotExisting => {
+                hashed_state.accounts.insert(hashed_address, None);
+                hashed_state
+                    .storages
+                    .insert(hashed_address, HashedStorage::new(true));
+            }
+            AccountState::Touched | AccountState::StorageCleared => {
+                hashed_state
+                    .accounts
+                    .insert(hashed_address, Some(into_reth_acc(account.info.clone())));
+                let mut storage =
+                    HashedStorage::new(account.account_state == AccountState::StorageCleared);
+                for (slot, value) in &account.storage {
+                    storage.storage.insert(keccak256(B256::from(*slot)), *value);
+                }
+                hashed_state.storages.insert(hashed_address, storage);
+            }
+        }
+    }
+
+    hashed_state
+}
+
+/// Credits the withdrawals to their recipients on top of `hashed_state`, the state left
+/// by the transactions of the block. Accounts the transactions didn't touch are read
+/// from `state`.
+pub fn apply_withdrawals(
+    hashed_state: &mut HashedPostState,
+    state: &impl AccountReader,
+    withdrawals: &[Withdrawal],
+) -> ProviderResult<()> {
+    // zero amount withdrawals don't touch the recipient, keeping empty accounts out of the trie
+    for withdrawal in withdrawals.iter().filter(|w| w.amount > 0) {
+        let hashed_address = keccak256(withdrawal.address);
+        let account = match hashed_state.accounts.get(&hashed_address) {
+            Some(account) => *account,
+            None => state.basic_account(withdrawal.address)?,
+        };
+        let mut account = account.unwrap_or_default();
+        account.balance += withdrawal.amount_wei();
+        hashed_state.accounts.insert(hashed_address, Some(account));
+    }
+    Ok(())
+}
+
+/// Computes the state root of the state after `parent` with `hashed_state` applied on
+/// top of it.
+///
+/// The trie tables only hold the state of the database tip, so the changes of the
+/// blocks after `parent` are reverted first, the way reth computes historical roots.
+pub fn compute_state_root(
+    provider: &RethProvider,
+    parent: &SealedHeader,
+    hashed_state: &HashedPostState,
+) -> Result<StateRootOutcome, PbbError> {
+    let start = Instant::now();
+    let provider_ro = provider.database_provider_ro()?;
+    if provider_ro.block_hash(parent.number)? != Some(parent.hash()) {
+        return Err(PbbError::NonCanonicalParent {
+            number: parent.number,
+            hash: parent.hash(),
+        });
+    }
+    let mut post_state = HashedPostState::from_reverts(provider_ro.tx_ref(), parent.number + 1)
+        .map_err(|e| PbbError::StateRoot(e.into()))?;
+    post_state.extend(hashed_state.clone());
+    let state_root = post_state
+        .state_root(provider_ro.tx_ref())
+        .map_err(|e| PbbError::StateRoot(e.into()))?;
+    let elapsed = start.elapsed();
+
+    Ok(StateRootOutcome {
+        state_root,
+        elapsed,
+    })
+}
+
+/// Logs how long the state root took relative to transaction execution
+pub fn log_state_root_timing(execution: Duration, outcome: &StateRootOutcome) {
+    let ratio = outcome.elapsed.as_secs_f64() / execution.as_secs_f64().max(f64::EPSILON);
+    info!(
+        "state root {} computed in {:?} (execution took {:?}, {:.2}x)",
+        outcome.state_root, outcome.elapsed, execution, ratio
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use reth_primitives::Address;
+    use reth_primitives::U256;
+
+    use super::*;
+    use crate::test_utils::MockAccounts;
+
+    const ALICE: Address = Address::repeat_byte(0xa1);
+    const BOB: Address = Address::repeat_byte(0xb0);
+
+    const GWEI: u64 = 1_000_000_000;
+
+    fn withdrawal(address: Address, amount: u64) -> Withdrawal {
+        Withdrawal {
+            address,
+            amount,
+            ..Default::default()
+        }
+    }
+
+    fn account(hashed_state: &HashedPostState, address: Address) -> Option<Account> {
+        hashed_state
+            .accounts
+            .get(&keccak256(address))
+            .copied()
+            .flatten()
+    }
+
+    #[test]
+    fn converts_withdrawal_amounts_from_gwei() {
+        let mut state = MockAccounts::default().with_nonce(ALICE, 3);
+        state.0.get_mut(&ALICE).unwrap().balance = U256::from(1);
+        let mut hashed_state = HashedPostState::default();
+
+        apply_withdrawals(&mut hashed_state, &state, &[withdrawal(ALICE, 5)]).unwrap();
+        let alice = account(&hashed_state, ALICE).unwrap();
+        assert_eq!(alice.balance, U256::from(5 * GWEI + 1));
+        assert_eq!(alice.nonce, 3);
+    }
+
+    #[test]
+    fn adds_up_the_withdrawals_to_an_address() {
+        let state = MockAccounts::default().with_nonce(ALICE, 0);
+        // the block already changed the account, withdrawals apply on top of the change
+        let mut hashed_state = HashedPostState::default();
+        hashed_state.accounts.insert(
+            keccak256(ALICE),
+            Some(Account {
+                nonce: 1,
+                balance: U256::from(7),
+                bytecode_hash: None,
+            }),
+        );
+
+        apply_withdrawals(
+            &mut hashed_state,
+            &state,
+            &[withdrawal(ALICE, 1), withdrawal(ALICE, 2)],
+        )
+        .unwrap();
+        let alice = account(&hashed_state, ALICE).unwrap();
+        assert_eq!(alice.balance, U256::from(3 * GWEI + 7));
+        assert_eq!(alice.nonce, 1);
+    }
+
+    #[test]
+    fn creates_the_accounts_that_do_not_exist_yet() {
+        let state = MockAccounts::default();
+        let mut hashed_state = HashedPostState::default();
+
+        apply_withdrawals(
+            &mut hashed_state,
+            &state,
+            &[withdrawal(ALICE, 2), withdrawal(BOB, 0)],
+        )
+        .unwrap();
+        assert_eq!(
+            account(&hashed_state, ALICE),
+            Some(Account {
+                balance: U256::from(2 * GWEI),
+                ..Default::default()
+            })
+        );
+        // a zero withdrawal doesn't create an empty account
+        assert!(!hashed_state.accounts.contains_key(&keccak256(BOB)));
+    }
+}
@@ -0,0 +1,174 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use pevm::AccountBasic;
+use pevm::EvmCode;
+use pevm::Storage;
+use reth_primitives::Address;
+use reth_primitives::B256;
+use reth_primitives::U256;
+use reth_provider::StateProviderBox;
+use reth_revm::database::StateProviderDatabase;
+use reth_revm::db::AccountState;
+use reth_revm::db::CacheDB;
+use reth_revm::primitives::KECCAK_EMPTY;
+use reth_revm::DatabaseRef;
+
+use crate::error::PbbError;
+use crate::utils::bytecode_to_evmcode;
+
+/// pevm storage backed by a reth state provider.
+///
+/// Accounts, code, storage slots and block hashes are read from the
+/// underlying state on first access and cached, so every worker thread of a
+/// parallel execution shares the same view of the latest state.
+pub struct RethStorage {
+    db: StateProviderDatabase<StateProviderBox>,
+    accounts: RwLock<HashMap<Address, Option<AccountBasic>>>,
+    codes: RwLock<HashMap<B256, Option<EvmCode>>>,
+    storage: RwLock<HashMap<(Address, U256), U256>>,
+    block_hashes: RwLock<HashMap<U256, B256>>,
+}
+
+impl RethStorage {
+    /// Creates a new storage over the given state provider
+    pub fn new(state: StateProviderBox) -> Self {
+        Self {
+            db: StateProviderDatabase::new(state),
+            accounts: RwLock::default(),
+            codes: RwLock::default(),
+            storage: RwLock::default(),
+            block_hashes: RwLock::default(),
+        }
+    }
+
+    /// Creates a new storage over the state of `db`, with the changes committed to it
+    /// on top, like the ones of the system calls run before the transactions
+    pub fn with_changes(
+        db: CacheDB<StateProviderDatabase<StateProviderBox>>,
+    ) -> Result<Self, PbbError> {
+        let storage = Self {
+            db: db.db,
+            accounts: RwLock::default(),
+            codes: RwLock::default(),
+            storage: RwLock::default(),
+            block_hashes: RwLock::default(),
+        };
+
+        for (address, account) in db.accounts {
+            let basic = match account.account_state {
+                AccountState::None => continue,
+                AccountState::NotExisting => None,
+                AccountState::Touched | AccountState::StorageCleared => {
+                    let code_hash = account.info.code_hash;
+                    let code = if code_hash == KECCAK_EMPTY {
+                        None
+                    } else {
+                        storage.code_by_hash(&code_hash)?
+                    };
+                    Some(AccountBasic {
+                        balance: account.info.balance,
+                        nonce: account.info.nonce,
+                        code_hash: Some(code_hash),
+                        code,
+                    })
+                }
+            };
+            storage.accounts.write().unwrap().insert(address, basic);
+
+            let mut slots = storage.storage.write().unwrap();
+            for (index, value) in account.storage {
+                slots.insert((address, index), value);
+            }
+        }
+
+        Ok(storage)
+    }
+
+    /// Returns the underlying reth database
+    pub fn db(&self) -> &StateProviderDatabase<StateProviderBox> {
+        &self.db
+    }
+}
+
+impl Storage for RethStorage {
+    type Error = PbbError;
+
+    fn basic(&self, address: &Address) -> Result<Option<AccountBasic>, Self::Error> {
+        if let Some(account) = self.accounts.read().unwrap().get(address) {
+            return Ok(account.clone());
+        }
+
+        let account = match self.db.basic_ref(*address)? {
+            Some(info) => {
+                let code = if info.code_hash == KECCAK_EMPTY {
+                    None
+                } else {
+                    self.code_by_hash(&info.code_hash)?
+                };
+                Some(AccountBasic {
+                    balance: info.balance,
+                    nonce: info.nonce,
+                    code_hash: Some(info.code_hash),
+                    code,
+                })
+            }
+            None => None,
+        };
+
+        self.accounts
+            .write()
+            .unwrap()
+            .insert(*address, account.clone());
+        Ok(account)
+    }
+
+    fn code_by_hash(&self, code_hash: &B256) -> Result<Option<EvmCode>, Self::Error> {
+        if let Some(code) = self.codes.read().unwrap().get(code_hash) {
+            return Ok(code.clone());
+        }
+
+        let bytecode = self.db.code_by_hash_ref(*code_hash)?;
+        let code = if bytecode.is_empty() {
+            None
+        } else {
+            Some(bytecode_to_evmcode(bytecode)?)
+        };
+
+        self.codes.write().unwrap().insert(*code_hash, code.clone());
+        Ok(code)
+    }
+
+    fn has_storage(&self, address: &Address) -> Result<bool, Self::Error> {
+        // reth does not expose storage roots through the state provider, so any
+        // account with code is treated as possibly having storage
+        Ok(self
+            .basic(address)?
+            .is_some_and(|account| account.code_hash.is_some_and(|hash| hash != KECCAK_EMPTY)))
+    }
+
+    fn storage(&self, address: &Address, index: &U256) -> Result<U256, Self::Error> {
+        if let Some(value) = self.storage.read().unwrap().get(&(*address, *index)) {
+            return Ok(*value);
+        }
+
+        let value = self.db.storage_ref(*address, *index)?;
+
+        self.storage
+            .write()
+            .unwrap()
+            .insert((*address, *index), value);
+        Ok(value)
+    }
+
+    fn block_hash(&self, number: &U256) -> Result<B256, Self::Error> {
+        if let Some(hash) = self.block_hashes.read().unwrap().get(number) {
+            return Ok(*hash);
+        }
+
+        let hash = self.db.block_hash_ref(*number)?;
+
+        self.block_hashes.write().unwrap().insert(*number, hash);
+        Ok(hash)
+    }
+}
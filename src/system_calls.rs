@@ -0,0 +1,66 @@
+use reth_chainspec::ChainSpec;
+use reth_evm::ConfigureEvm;
+use reth_node_ethereum::EthEvmConfig;
+use reth_primitives::revm::config::revm_spec_by_timestamp_after_merge;
+use reth_primitives::SealedHeader;
+use reth_primitives::U256;
+use reth_provider::ProviderError;
+use reth_revm::db::CacheDB;
+use reth_revm::primitives::BlockEnv;
+use reth_revm::primitives::CfgEnv;
+use reth_revm::primitives::CfgEnvWithHandlerCfg;
+use reth_revm::primitives::EnvWithHandlerCfg;
+use reth_revm::primitives::TxEnv;
+use reth_revm::state_change::apply_beacon_root_contract_call;
+use reth_revm::state_change::apply_blockhashes_update;
+use reth_revm::DatabaseRef;
+use reth_rpc_types::engine::PayloadAttributes;
+
+use crate::error::PbbError;
+
+/// Runs the system calls that open the block built on top of `parent`, committing
+/// their changes to `db` before any transaction executes:
+/// - EIP-2935 stores the parent hash in the history storage contract, from Prague
+/// - EIP-4788 stores the parent beacon block root in the beacon roots contract, from Cancun
+///
+/// Each call only runs once its fork is active at the timestamp of the block.
+pub fn apply_pre_block_calls<DB>(
+    chain_spec: &ChainSpec,
+    parent: &SealedHeader,
+    payload_attributes: &PayloadAttributes,
+    db: &mut CacheDB<DB>,
+) -> Result<(), PbbError>
+where
+    DB: DatabaseRef<Error = ProviderError>,
+{
+    let block_number = parent.number + 1;
+    let timestamp = payload_attributes.timestamp;
+
+    apply_blockhashes_update(db, chain_spec, timestamp, block_number, parent.hash())
+        .map_err(|e| PbbError::Execution(e.into()))?;
+
+    let spec_id = revm_spec_by_timestamp_after_merge(chain_spec, timestamp);
+    let cfg = CfgEnvWithHandlerCfg::new_with_spec_id(
+        CfgEnv::default().with_chain_id(chain_spec.chain().id()),
+        spec_id,
+    );
+    // system calls pay no gas, only the fields the called contracts can read matter
+    let block_env = BlockEnv {
+        number: U256::from(block_number),
+        timestamp: U256::from(timestamp),
+        prevrandao: Some(payload_attributes.prev_randao),
+        ..Default::default()
+    };
+    let env = EnvWithHandlerCfg::new_with_cfg_env(cfg, block_env, TxEnv::default());
+    let mut evm = EthEvmConfig::default().evm_with_env(db, env);
+    apply_beacon_root_contract_call(
+        chain_spec,
+        timestamp,
+        block_number,
+        payload_attributes.parent_beacon_block_root,
+        &mut evm,
+    )
+    .map_err(|e| PbbError::Execution(e.into()))?;
+
+    Ok(())
+}
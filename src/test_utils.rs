@@ -0,0 +1,110 @@
+use std::collections::HashMap;
+
+use reth_primitives::sign_message;
+use reth_primitives::Account;
+use reth_primitives::Address;
+use reth_primitives::Signature;
+use reth_primitives::Transaction;
+use reth_primitives::TransactionSigned;
+use reth_primitives::TransactionSignedEcRecovered;
+use reth_primitives::TxEip1559;
+use reth_primitives::TxEip4844;
+use reth_primitives::TxKind;
+use reth_primitives::B256;
+use reth_provider::AccountReader;
+use reth_provider::ProviderResult;
+
+/// Gas limit of a plain transfer
+pub const TRANSFER_GAS: u64 = 21_000;
+
+/// Transaction of `sender` paying at most `max_fee_per_gas` with a tip of `tip`.
+///
+/// The signature is left empty, the sender is taken as recovered.
+pub fn tx(
+    sender: Address,
+    nonce: u64,
+    gas_limit: u64,
+    max_fee_per_gas: u128,
+    tip: u128,
+) -> TransactionSignedEcRecovered {
+    recovered(
+        sender,
+        Transaction::Eip1559(TxEip1559 {
+            chain_id: 1,
+            nonce,
+            gas_limit,
+            max_fee_per_gas,
+            max_priority_fee_per_gas: tip,
+            to: TxKind::Call(Address::repeat_byte(0xee)),
+            ..Default::default()
+        }),
+    )
+}
+
+/// Blob transaction of `sender` carrying `blobs` blobs
+pub fn blob_tx(
+    sender: Address,
+    nonce: u64,
+    blobs: usize,
+    max_fee_per_blob_gas: u128,
+) -> TransactionSignedEcRecovered {
+    recovered(
+        sender,
+        Transaction::Eip4844(TxEip4844 {
+            chain_id: 1,
+            nonce,
+            gas_limit: TRANSFER_GAS,
+            max_fee_per_gas: 100,
+            max_priority_fee_per_gas: 1,
+            to: Address::repeat_byte(0xee),
+            blob_versioned_hashes: vec![B256::repeat_byte(0x01); blobs],
+            max_fee_per_blob_gas,
+            ..Default::default()
+        }),
+    )
+}
+
+/// Transfer signed with `secret_key`, so that its sender can be recovered
+pub fn signed_tx(secret_key: B256, nonce: u64) -> TransactionSigned {
+    let transaction = Transaction::Eip1559(TxEip1559 {
+        chain_id: 1,
+        nonce,
+        gas_limit: TRANSFER_GAS,
+        max_fee_per_gas: 100,
+        max_priority_fee_per_gas: 1,
+        to: TxKind::Call(Address::repeat_byte(0xee)),
+        ..Default::default()
+    });
+    let signature = sign_message(secret_key, transaction.signature_hash()).unwrap();
+    TransactionSigned::from_transaction_and_signature(transaction, signature)
+}
+
+fn recovered(sender: Address, transaction: Transaction) -> TransactionSignedEcRecovered {
+    let signed =
+        TransactionSigned::from_transaction_and_signature(transaction, Signature::default());
+    TransactionSignedEcRecovered::from_signed_transaction(signed, sender)
+}
+
+/// Accounts of a state, by address
+#[derive(Debug, Default)]
+pub struct MockAccounts(pub HashMap<Address, Account>);
+
+impl MockAccounts {
+    /// Adds an account with `nonce`
+    pub fn with_nonce(mut self, address: Address, nonce: u64) -> Self {
+        self.0.insert(
+            address,
+            Account {
+                nonce,
+                ..Default::default()
+            },
+        );
+        self
+    }
+}
+
+impl AccountReader for MockAccounts {
+    fn basic_account(&self, address: Address) -> ProviderResult<Option<Account>> {
+        Ok(self.0.get(&address).copied())
+    }
+}
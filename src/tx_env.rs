@@ -0,0 +1,361 @@
+use pevm::TransactTo;
+use reth_primitives::AccessList;
+use reth_primitives::Address;
+use reth_primitives::Bytes;
+use reth_primitives::Transaction;
+use reth_primitives::TransactionSignedEcRecovered;
+use reth_primitives::TxKind;
+use reth_primitives::B256;
+use reth_primitives::U256;
+
+/// What the EVM needs to know about a transaction, whatever executes it.
+///
+/// Every transaction type is mapped here once, the execution backends only convert
+/// these fields into their own transaction environment.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TxFields {
+    pub caller: Address,
+    pub gas_limit: u64,
+    /// Gas price of legacy transactions, max fee per gas otherwise
+    pub gas_price: U256,
+    pub gas_priority_fee: Option<U256>,
+    pub to: TxKind,
+    pub value: U256,
+    pub data: Bytes,
+    pub chain_id: Option<u64>,
+    pub nonce: u64,
+    pub access_list: Vec<(Address, Vec<U256>)>,
+    pub blob_hashes: Vec<B256>,
+    pub max_fee_per_blob_gas: Option<U256>,
+}
+
+impl From<&TransactionSignedEcRecovered> for TxFields {
+    fn from(tx_signed: &TransactionSignedEcRecovered) -> Self {
+        let caller = tx_signed.signer();
+        // a new transaction type, like EIP-7702 set-code transactions once reth has
+        // them, only needs a new arm here
+        match &tx_signed.transaction {
+            Transaction::Legacy(tx) => Self {
+                caller,
+                gas_limit: tx.gas_limit,
+                gas_price: U256::from(tx.gas_price),
+                gas_priority_fee: None,
+                to: tx.to,
+                value: tx.value,
+                data: tx.input.clone(),
+                chain_id: tx.chain_id,
+                nonce: tx.nonce,
+                ..Default::default()
+            },
+            Transaction::Eip2930(tx) => Self {
+                caller,
+                gas_limit: tx.gas_limit,
+                gas_price: U256::from(tx.gas_price),
+                gas_priority_fee: None,
+                to: tx.to,
+                value: tx.value,
+                data: tx.input.clone(),
+                chain_id: Some(tx.chain_id),
+                nonce: tx.nonce,
+                access_list: access_list_fields(&tx.access_list),
+                ..Default::default()
+            },
+            Transaction::Eip1559(tx) => Self {
+                caller,
+                gas_limit: tx.gas_limit,
+                gas_price: U256::from(tx.max_fee_per_gas),
+                gas_priority_fee: Some(U256::from(tx.max_priority_fee_per_gas)),
+                to: tx.to,
+                value: tx.value,
+                data: tx.input.clone(),
+                chain_id: Some(tx.chain_id),
+                nonce: tx.nonce,
+                access_list: access_list_fields(&tx.access_list),
+                ..Default::default()
+            },
+            Transaction::Eip4844(tx) => Self {
+                caller,
+                gas_limit: tx.gas_limit,
+                gas_price: U256::from(tx.max_fee_per_gas),
+                gas_priority_fee: Some(U256::from(tx.max_priority_fee_per_gas)),
+                to: TxKind::Call(tx.to),
+                value: tx.value,
+                data: tx.input.clone(),
+                chain_id: Some(tx.chain_id),
+                nonce: tx.nonce,
+                access_list: access_list_fields(&tx.access_list),
+                blob_hashes: tx.blob_versioned_hashes.clone(),
+                max_fee_per_blob_gas: Some(U256::from(tx.max_fee_per_blob_gas)),
+            },
+        }
+    }
+}
+
+fn access_list_fields(access_list: &AccessList) -> Vec<(Address, Vec<U256>)> {
+    access_list
+        .0
+        .iter()
+        .map(|item| {
+            (
+                item.address,
+                item.storage_keys
+                    .iter()
+                    .map(|key| U256::from_be_bytes(key.0))
+                    .collect(),
+            )
+        })
+        .collect()
+}
+
+/// Transaction environment of an execution backend
+pub trait FromTxFields {
+    fn from_tx_fields(fields: TxFields) -> Self;
+}
+
+impl FromTxFields for pevm::TxEnv {
+    fn from_tx_fields(fields: TxFields) -> Self {
+        Self {
+            caller: fields.caller,
+            gas_limit: fields.gas_limit,
+            gas_price: fields.gas_price,
+            gas_priority_fee: fields.gas_priority_fee,
+            transact_to: match fields.to {
+                TxKind::Call(to) => TransactTo::Call(to),
+                TxKind::Create => TransactTo::Create,
+            },
+            value: fields.value,
+            data: fields.data,
+            chain_id: fields.chain_id,
+            nonce: Some(fields.nonce),
+            access_list: fields.access_list,
+            blob_hashes: fields.blob_hashes,
+            max_fee_per_blob_gas: fields.max_fee_per_blob_gas,
+            ..Default::default()
+        }
+    }
+}
+
+impl FromTxFields for reth_revm::primitives::TxEnv {
+    fn from_tx_fields(fields: TxFields) -> Self {
+        Self {
+            caller: fields.caller,
+            gas_limit: fields.gas_limit,
+            gas_price: fields.gas_price,
+            gas_priority_fee: fields.gas_priority_fee,
+            transact_to: fields.to,
+            value: fields.value,
+            data: fields.data,
+            chain_id: fields.chain_id,
+            nonce: Some(fields.nonce),
+            access_list: fields.access_list,
+            blob_hashes: fields.blob_hashes,
+            max_fee_per_blob_gas: fields.max_fee_per_blob_gas,
+            ..Default::default()
+        }
+    }
+}
+
+/// Builds the transaction environment `T` of an execution backend for `tx_signed`
+pub fn tx_env<T: FromTxFields>(tx_signed: &TransactionSignedEcRecovered) -> T {
+    T::from_tx_fields(TxFields::from(tx_signed))
+}
+
+#[cfg(test)]
+mod tests {
+    use reth_primitives::AccessListItem;
+    use reth_primitives::Signature;
+    use reth_primitives::TransactionSigned;
+    use reth_primitives::TxEip1559;
+    use reth_primitives::TxEip2930;
+    use reth_primitives::TxEip4844;
+    use reth_primitives::TxLegacy;
+
+    use super::*;
+
+    const CALLER: Address = Address::repeat_byte(0xa1);
+    const TO: Address = Address::repeat_byte(0xee);
+
+    fn recovered(transaction: Transaction) -> TransactionSignedEcRecovered {
+        TransactionSignedEcRecovered::from_signed_transaction(
+            TransactionSigned::from_transaction_and_signature(transaction, Signature::default()),
+            CALLER,
+        )
+    }
+
+    fn access_list() -> AccessList {
+        AccessList(vec![AccessListItem {
+            address: TO,
+            storage_keys: vec![B256::with_last_byte(7)],
+        }])
+    }
+
+    /// Checks that `tx` maps to `expected` and that both backends get these fields
+    fn assert_tx_fields(tx: &TransactionSignedEcRecovered, expected: TxFields) {
+        assert_eq!(TxFields::from(tx), expected);
+
+        let pevm_env: pevm::TxEnv = tx_env(tx);
+        assert_eq!(pevm_env.caller, expected.caller);
+        assert_eq!(pevm_env.gas_limit, expected.gas_limit);
+        assert_eq!(pevm_env.gas_price, expected.gas_price);
+        assert_eq!(pevm_env.gas_priority_fee, expected.gas_priority_fee);
+        let transact_to = match expected.to {
+            TxKind::Call(to) => TransactTo::Call(to),
+            TxKind::Create => TransactTo::Create,
+        };
+        assert_eq!(pevm_env.transact_to, transact_to);
+        assert_eq!(pevm_env.value, expected.value);
+        assert_eq!(pevm_env.data, expected.data);
+        assert_eq!(pevm_env.chain_id, expected.chain_id);
+        assert_eq!(pevm_env.nonce, Some(expected.nonce));
+        assert_eq!(pevm_env.access_list, expected.access_list);
+        assert_eq!(pevm_env.blob_hashes, expected.blob_hashes);
+        assert_eq!(pevm_env.max_fee_per_blob_gas, expected.max_fee_per_blob_gas);
+
+        let reth_env: reth_revm::primitives::TxEnv = tx_env(tx);
+        assert_eq!(reth_env.caller, expected.caller);
+        assert_eq!(reth_env.gas_limit, expected.gas_limit);
+        assert_eq!(reth_env.gas_price, expected.gas_price);
+        assert_eq!(reth_env.gas_priority_fee, expected.gas_priority_fee);
+        assert_eq!(reth_env.transact_to, expected.to);
+        assert_eq!(reth_env.value, expected.value);
+        assert_eq!(reth_env.data, expected.data);
+        assert_eq!(reth_env.chain_id, expected.chain_id);
+        assert_eq!(reth_env.nonce, Some(expected.nonce));
+        assert_eq!(reth_env.access_list, expected.access_list);
+        assert_eq!(reth_env.blob_hashes, expected.blob_hashes);
+        assert_eq!(reth_env.max_fee_per_blob_gas, expected.max_fee_per_blob_gas);
+    }
+
+    #[test]
+    fn legacy_transactions() {
+        let tx = recovered(Transaction::Legacy(TxLegacy {
+            chain_id: Some(1),
+            nonce: 3,
+            gas_price: 10,
+            gas_limit: 21_000,
+            to: TxKind::Call(TO),
+            input: Bytes::from_static(&[0x01]),
+            ..Default::default()
+        }));
+        let expected = TxFields {
+            caller: CALLER,
+            gas_limit: 21_000,
+            gas_price: U256::from(10),
+            gas_priority_fee: None,
+            to: TxKind::Call(TO),
+            data: Bytes::from_static(&[0x01]),
+            chain_id: Some(1),
+            nonce: 3,
+            ..Default::default()
+        };
+        assert_tx_fields(&tx, expected);
+    }
+
+    #[test]
+    fn pre_eip155_contract_creations() {
+        let tx = recovered(Transaction::Legacy(TxLegacy {
+            chain_id: None,
+            gas_price: 10,
+            gas_limit: 100_000,
+            to: TxKind::Create,
+            ..Default::default()
+        }));
+        let expected = TxFields {
+            caller: CALLER,
+            gas_limit: 100_000,
+            gas_price: U256::from(10),
+            to: TxKind::Create,
+            chain_id: None,
+            ..Default::default()
+        };
+        assert_tx_fields(&tx, expected);
+    }
+
+    #[test]
+    fn eip2930_transactions() {
+        let tx = recovered(Transaction::Eip2930(TxEip2930 {
+            chain_id: 1,
+            nonce: 4,
+            gas_price: 10,
+            gas_limit: 30_000,
+            to: TxKind::Call(TO),
+            value: U256::from(5),
+            access_list: access_list(),
+            ..Default::default()
+        }));
+        let expected = TxFields {
+            caller: CALLER,
+            gas_limit: 30_000,
+            gas_price: U256::from(10),
+            gas_priority_fee: None,
+            to: TxKind::Call(TO),
+            value: U256::from(5),
+            chain_id: Some(1),
+            nonce: 4,
+            access_list: vec![(TO, vec![U256::from(7)])],
+            ..Default::default()
+        };
+        assert_tx_fields(&tx, expected);
+    }
+
+    #[test]
+    fn eip1559_transactions() {
+        let tx = recovered(Transaction::Eip1559(TxEip1559 {
+            chain_id: 1,
+            nonce: 5,
+            gas_limit: 30_000,
+            max_fee_per_gas: 20,
+            max_priority_fee_per_gas: 2,
+            to: TxKind::Call(TO),
+            value: U256::from(5),
+            access_list: access_list(),
+            ..Default::default()
+        }));
+        let expected = TxFields {
+            caller: CALLER,
+            gas_limit: 30_000,
+            gas_price: U256::from(20),
+            gas_priority_fee: Some(U256::from(2)),
+            to: TxKind::Call(TO),
+            value: U256::from(5),
+            chain_id: Some(1),
+            nonce: 5,
+            access_list: vec![(TO, vec![U256::from(7)])],
+            ..Default::default()
+        };
+        assert_tx_fields(&tx, expected);
+    }
+
+    #[test]
+    fn eip4844_transactions() {
+        let blob_hashes = vec![B256::repeat_byte(0x01), B256::repeat_byte(0x02)];
+        let tx = recovered(Transaction::Eip4844(TxEip4844 {
+            chain_id: 1,
+            nonce: 6,
+            gas_limit: 30_000,
+            max_fee_per_gas: 20,
+            max_priority_fee_per_gas: 2,
+            to: TO,
+            value: U256::from(5),
+            access_list: access_list(),
+            blob_versioned_hashes: blob_hashes.clone(),
+            max_fee_per_blob_gas: 3,
+            ..Default::default()
+        }));
+        let expected = TxFields {
+            caller: CALLER,
+            gas_limit: 30_000,
+            gas_price: U256::from(20),
+            gas_priority_fee: Some(U256::from(2)),
+            to: TxKind::Call(TO),
+            value: U256::from(5),
+            data: Bytes::new(),
+            chain_id: Some(1),
+            nonce: 6,
+            access_list: vec![(TO, vec![U256::from(7)])],
+            blob_hashes,
+            max_fee_per_blob_gas: Some(U256::from(3)),
+        };
+        assert_tx_fields(&tx, expected);
+    }
+}
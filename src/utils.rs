@@ -1,150 +1,47 @@
+use std::fmt;
 use std::sync::Arc;
 
-use pevm::{EvmCode, TransactTo};
-use reth_chainspec::{ChainSpec, HOLESKY};
+use log::warn;
+use pevm::EvmCode;
+use reth_chainspec::ChainSpec;
 use reth_primitives::revm_primitives::Bytecode;
-use reth_primitives::{Bytes, JumpTable, Transaction, TransactionSigned, TxKind, U256};
+use reth_primitives::{Bytes, JumpTable, TransactionSignedEcRecovered};
 use reth_revm::interpreter::opcode;
 use reth_revm::primitives::bitvec::bitvec;
 use reth_revm::primitives::bitvec::order::Lsb0;
 use reth_revm::primitives::bitvec::vec::BitVec;
 use reth_revm::primitives::LegacyAnalyzedBytecode;
+use reth_revm::primitives::SpecId;
 
-pub fn get_tx_env(tx_signed: TransactionSigned) -> pevm::TxEnv {
-    let mut tx_env = pevm::TxEnv::default();
+use crate::error::PbbError;
 
-    tx_env.caller = tx_signed.recover_signer().unwrap();
-
-    match tx_signed.as_ref() {
-        Transaction::Legacy(tx) => {
-            tx_env.gas_limit = tx.gas_limit;
-            tx_env.gas_price = U256::from(tx.gas_price);
-            tx_env.gas_priority_fee = None;
-            tx_env.transact_to = match tx.to {
-                TxKind::Call(to) => TransactTo::Call(to),
-                TxKind::Create => TransactTo::Create,
-            };
-            tx_env.value = tx.value;
-            tx_env.data = tx.input.clone();
-            tx_env.chain_id = tx.chain_id;
-            tx_env.nonce = Some(tx.nonce);
-            tx_env.access_list.clear();
-            tx_env.blob_hashes.clear();
-            tx_env.max_fee_per_blob_gas.take();
-        }
-        Transaction::Eip2930(tx) => {
-            tx_env.gas_limit = tx.gas_limit;
-            tx_env.gas_price = U256::from(tx.gas_price);
-            tx_env.gas_priority_fee = None;
-            tx_env.transact_to = match tx.to {
-                TxKind::Call(to) => TransactTo::Call(to),
-                TxKind::Create => TransactTo::Create,
-            };
-            tx_env.value = tx.value;
-            tx_env.data = tx.input.clone();
-            tx_env.chain_id = Some(tx.chain_id);
-            tx_env.nonce = Some(tx.nonce);
-            tx_env.access_list = tx
-                .access_list
-                .0
-                .iter()
-                .map(|l| {
-                    (
-                        l.address,
-                        l.storage_keys
-                            .iter()
-                            .map(|k| U256::from_be_bytes(k.0))
-                            .collect(),
-                    )
-                })
-                .collect();
-            tx_env.blob_hashes.clear();
-            tx_env.max_fee_per_blob_gas.take();
-        }
-        Transaction::Eip1559(tx) => {
-            tx_env.gas_limit = tx.gas_limit;
-            tx_env.gas_price = U256::from(tx.max_fee_per_gas);
-            tx_env.gas_priority_fee = Some(U256::from(tx.max_priority_fee_per_gas));
-            tx_env.transact_to = match tx.to {
-                TxKind::Call(to) => TransactTo::Call(to),
-                TxKind::Create => TransactTo::Create,
-            };
-            tx_env.value = tx.value;
-            tx_env.data = tx.input.clone();
-            tx_env.chain_id = Some(tx.chain_id);
-            tx_env.nonce = Some(tx.nonce);
-            tx_env.access_list = tx
-                .access_list
-                .0
-                .iter()
-                .map(|l| {
-                    (
-                        l.address,
-                        l.storage_keys
-                            .iter()
-                            .map(|k| U256::from_be_bytes(k.0))
-                            .collect(),
-                    )
-                })
-                .collect();
-            tx_env.blob_hashes.clear();
-            tx_env.max_fee_per_blob_gas.take();
-        }
-        Transaction::Eip4844(tx) => {
-            tx_env.gas_limit = tx.gas_limit;
-            tx_env.gas_price = U256::from(tx.max_fee_per_gas);
-            tx_env.gas_priority_fee = Some(U256::from(tx.max_priority_fee_per_gas));
-            tx_env.transact_to = TransactTo::Call(tx.to);
-            tx_env.value = tx.value;
-            tx_env.data = tx.input.clone();
-            tx_env.chain_id = Some(tx.chain_id);
-            tx_env.nonce = Some(tx.nonce);
-            tx_env.access_list = tx
-                .access_list
-                .0
-                .iter()
-                .map(|l| {
-                    (
-                        l.address,
-                        l.storage_keys
-                            .iter()
-                            .map(|k| U256::from_be_bytes(k.0))
-                            .collect(),
-                    )
-                })
-                .collect();
-            tx_env.blob_hashes.clone_from(&tx.blob_versioned_hashes);
-            tx_env.max_fee_per_blob_gas = Some(U256::from(tx.max_fee_per_blob_gas));
-        }
-    }
-    tx_env
-}
-
-pub fn bytecode_to_evmcode(bytecode: Bytecode) -> EvmCode {
+/// Converts legacy bytecode into the analyzed code pevm executes, failing on the
+/// formats pevm can't execute, like EOF
+pub fn bytecode_to_evmcode(bytecode: Bytecode) -> Result<EvmCode, PbbError> {
     let code = match bytecode {
         Bytecode::LegacyAnalyzed(code) => code,
-        Bytecode::LegacyRaw(_) => to_analysed(bytecode),
-        _ => unreachable!(),
+        Bytecode::LegacyRaw(bytecode) => to_analysed(bytecode),
+        other => {
+            return Err(PbbError::UnsupportedBytecode {
+                code_hash: other.hash_slow(),
+            })
+        }
     };
 
-    EvmCode {
+    Ok(EvmCode {
         bytecode: code.bytecode().clone(),
         original_len: code.original_len(),
         jump_table: code.jump_table().clone().0,
-    }
+    })
 }
 
-pub fn to_analysed(bytecode: Bytecode) -> LegacyAnalyzedBytecode {
-    let (bytes, len) = match bytecode {
-        Bytecode::LegacyRaw(bytecode) => {
-            let len = bytecode.len();
-            let mut padded_bytecode = Vec::with_capacity(len + 33);
-            padded_bytecode.extend_from_slice(&bytecode);
-            padded_bytecode.resize(len + 33, 0);
-            (Bytes::from(padded_bytecode), len)
-        }
-        _ => unreachable!(),
-    };
+/// Pads raw legacy bytecode and analyzes its jump destinations
+pub fn to_analysed(bytecode: Bytes) -> LegacyAnalyzedBytecode {
+    let len = bytecode.len();
+    let mut padded_bytecode = Vec::with_capacity(len + 33);
+    padded_bytecode.extend_from_slice(&bytecode);
+    padded_bytecode.resize(len + 33, 0);
+    let bytes = Bytes::from(padded_bytecode);
     let jump_table = analyze(bytes.as_ref());
 
     LegacyAnalyzedBytecode::new(bytes, len, jump_table)
@@ -179,125 +76,45 @@ fn analyze(code: &[u8]) -> JumpTable {
     JumpTable(Arc::new(jumps))
 }
 
-pub fn chain_spec() -> Arc<ChainSpec> {
-    Arc::new(ChainSpec {
-        chain: HOLESKY.chain.clone(),
-        genesis: HOLESKY.genesis.clone(),
-        hardforks: HOLESKY.hardforks.clone(),
-        genesis_hash: Some(HOLESKY.genesis_hash()),
-        paris_block_and_final_difficulty: HOLESKY.paris_block_and_final_difficulty,
-        deposit_contract: HOLESKY.deposit_contract.clone(),
-        base_fee_params: HOLESKY.base_fee_params.clone(),
-        prune_delete_limit: HOLESKY.prune_delete_limit,
-    })
-}
+/// Spec that pevm can not execute transactions for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnsupportedSpecError(pub SpecId);
 
-pub fn get_tx_env_reth(tx_signed: TransactionSigned) -> reth_revm::primitives::TxEnv {
-    let mut tx_env = reth_revm::primitives::TxEnv::default();
+impl fmt::Display for UnsupportedSpecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "spec {:?} is not supported by pevm", self.0)
+    }
+}
 
-    tx_env.caller = tx_signed.recover_signer().unwrap();
+impl std::error::Error for UnsupportedSpecError {}
 
-    match tx_signed.as_ref() {
-        Transaction::Legacy(tx) => {
-            tx_env.gas_limit = tx.gas_limit;
-            tx_env.gas_price = U256::from(tx.gas_price);
-            tx_env.gas_priority_fee = None;
-            tx_env.transact_to = match tx.to {
-                TxKind::Call(to) => TxKind::Call(to),
-                TxKind::Create => TxKind::Create,
-            };
-            tx_env.value = tx.value;
-            tx_env.data = tx.input.clone();
-            tx_env.chain_id = tx.chain_id;
-            tx_env.nonce = Some(tx.nonce);
-            tx_env.access_list.clear();
-            tx_env.blob_hashes.clear();
-            tx_env.max_fee_per_blob_gas.take();
-        }
-        Transaction::Eip2930(tx) => {
-            tx_env.gas_limit = tx.gas_limit;
-            tx_env.gas_price = U256::from(tx.gas_price);
-            tx_env.gas_priority_fee = None;
-            tx_env.transact_to = match tx.to {
-                TxKind::Call(to) => TxKind::Call(to),
-                TxKind::Create => TxKind::Create,
-            };
-            tx_env.value = tx.value;
-            tx_env.data = tx.input.clone();
-            tx_env.chain_id = Some(tx.chain_id);
-            tx_env.nonce = Some(tx.nonce);
-            tx_env.access_list = tx
-                .access_list
-                .0
-                .iter()
-                .map(|l| {
-                    (
-                        l.address,
-                        l.storage_keys
-                            .iter()
-                            .map(|k| U256::from_be_bytes(k.0))
-                            .collect(),
-                    )
-                })
-                .collect();
-            tx_env.blob_hashes.clear();
-            tx_env.max_fee_per_blob_gas.take();
-        }
-        Transaction::Eip1559(tx) => {
-            tx_env.gas_limit = tx.gas_limit;
-            tx_env.gas_price = U256::from(tx.max_fee_per_gas);
-            tx_env.gas_priority_fee = Some(U256::from(tx.max_priority_fee_per_gas));
-            tx_env.transact_to = match tx.to {
-                TxKind::Call(to) => TxKind::Call(to),
-                TxKind::Create => TxKind::Create,
-            };
-            tx_env.value = tx.value;
-            tx_env.data = tx.input.clone();
-            tx_env.chain_id = Some(tx.chain_id);
-            tx_env.nonce = Some(tx.nonce);
-            tx_env.access_list = tx
-                .access_list
-                .0
-                .iter()
-                .map(|l| {
-                    (
-                        l.address,
-                        l.storage_keys
-                            .iter()
-                            .map(|k| U256::from_be_bytes(k.0))
-                            .collect(),
-                    )
-                })
-                .collect();
-            tx_env.blob_hashes.clear();
-            tx_env.max_fee_per_blob_gas.take();
-        }
-        Transaction::Eip4844(tx) => {
-            tx_env.gas_limit = tx.gas_limit;
-            tx_env.gas_price = U256::from(tx.max_fee_per_gas);
-            tx_env.gas_priority_fee = Some(U256::from(tx.max_priority_fee_per_gas));
-            tx_env.transact_to = TxKind::Call(tx.to);
-            tx_env.value = tx.value;
-            tx_env.data = tx.input.clone();
-            tx_env.chain_id = Some(tx.chain_id);
-            tx_env.nonce = Some(tx.nonce);
-            tx_env.access_list = tx
-                .access_list
-                .0
-                .iter()
-                .map(|l| {
-                    (
-                        l.address,
-                        l.storage_keys
-                            .iter()
-                            .map(|k| U256::from_be_bytes(k.0))
-                            .collect(),
-                    )
-                })
-                .collect();
-            tx_env.blob_hashes.clone_from(&tx.blob_versioned_hashes);
-            tx_env.max_fee_per_blob_gas = Some(U256::from(tx.max_fee_per_blob_gas));
-        }
+/// Maps the revm spec used by reth onto the spec type of pevm
+pub fn pevm_spec_id(spec_id: SpecId) -> Result<pevm::SpecId, UnsupportedSpecError> {
+    match spec_id {
+        SpecId::MERGE => Ok(pevm::SpecId::MERGE),
+        SpecId::SHANGHAI => Ok(pevm::SpecId::SHANGHAI),
+        SpecId::CANCUN => Ok(pevm::SpecId::CANCUN),
+        _ => Err(UnsupportedSpecError(spec_id)),
     }
-    tx_env
+}
+
+/// Drops the transactions signed for another chain
+pub fn retain_chain_transactions(
+    txs: Vec<TransactionSignedEcRecovered>,
+    chain_spec: &ChainSpec,
+) -> Vec<TransactionSignedEcRecovered> {
+    let chain_id = chain_spec.chain().id();
+    txs.into_iter()
+        .filter(|tx| {
+            if tx.chain_id().is_some_and(|id| id != chain_id) {
+                warn!(
+                    "Dropping tx {} signed for chain {:?}",
+                    tx.hash(),
+                    tx.chain_id()
+                );
+                return false;
+            }
+            true
+        })
+        .collect()
 }